@@ -0,0 +1,267 @@
+use crate::notification_hub_client::NotificationRequest;
+use crate::platform::Platform;
+use crate::priority::Priority;
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+
+/// Builds an Apple Push Notification Service payload together with the
+/// `apns-*` headers and platform Azure expects for it.
+#[derive(Clone, Debug)]
+pub struct ApnsNotification {
+    title: Option<String>,
+    body: Option<String>,
+    badge: Option<u32>,
+    sound: Option<String>,
+    content_available: bool,
+    data: Map<String, Value>,
+    topic: Option<String>,
+    push_type: String,
+    priority: Priority,
+    collapse_id: Option<String>,
+    thread_id: Option<String>,
+    ttl: Option<Duration>,
+    content_type: Option<String>,
+}
+
+#[derive(Serialize)]
+struct ApnsPayload<'a> {
+    aps: Aps<'a>,
+    #[serde(flatten)]
+    data: &'a Map<String, Value>,
+}
+
+#[derive(Serialize)]
+struct Aps<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    alert: Option<Alert<'a>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    badge: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sound: Option<&'a str>,
+    #[serde(rename = "content-available", skip_serializing_if = "Option::is_none")]
+    content_available: Option<u8>,
+    #[serde(rename = "thread-id", skip_serializing_if = "Option::is_none")]
+    thread_id: Option<&'a str>,
+}
+
+#[derive(Serialize)]
+struct Alert<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    title: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    body: Option<&'a str>,
+}
+
+impl Default for ApnsNotification {
+    fn default() -> Self {
+        Self {
+            title: None,
+            body: None,
+            badge: None,
+            sound: None,
+            content_available: false,
+            data: Map::new(),
+            topic: None,
+            push_type: "alert".to_string(),
+            priority: Priority::High,
+            collapse_id: None,
+            thread_id: None,
+            ttl: None,
+            content_type: None,
+        }
+    }
+}
+
+impl ApnsNotification {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A background (silent) notification that wakes the app to process `data`
+    /// without alerting the user. Apple requires exactly this combination of
+    /// `apns-push-type: background`, `apns-priority: 5`, `content-available: 1`
+    /// and no alert, badge, or sound; `validate` reports any that are changed.
+    pub fn background(data: Map<String, Value>) -> Self {
+        Self {
+            content_available: true,
+            data,
+            push_type: "background".to_string(),
+            priority: Priority::Normal,
+            ..Self::default()
+        }
+    }
+
+    pub fn title(mut self, title: impl Into<String>) -> Self {
+        self.title = Some(title.into());
+        self
+    }
+
+    pub fn body(mut self, body: impl Into<String>) -> Self {
+        self.body = Some(body.into());
+        self
+    }
+
+    pub fn badge(mut self, badge: u32) -> Self {
+        self.badge = Some(badge);
+        self
+    }
+
+    pub fn sound(mut self, sound: impl Into<String>) -> Self {
+        self.sound = Some(sound.into());
+        self
+    }
+
+    pub fn content_available(mut self, content_available: bool) -> Self {
+        self.content_available = content_available;
+        self
+    }
+
+    /// Adds a custom key alongside the `aps` dictionary.
+    pub fn data(mut self, key: impl Into<String>, value: impl Into<Value>) -> Self {
+        self.data.insert(key.into(), value.into());
+        self
+    }
+
+    /// Sets the `apns-topic` header, usually the app's bundle identifier.
+    pub fn topic(mut self, topic: impl Into<String>) -> Self {
+        self.topic = Some(topic.into());
+        self
+    }
+
+    /// Sets the `apns-push-type` header. Defaults to `alert`.
+    pub fn push_type(mut self, push_type: impl Into<String>) -> Self {
+        self.push_type = push_type.into();
+        self
+    }
+
+    /// Sets the `apns-priority` header, `10` for high and `5` for normal.
+    /// Defaults to high.
+    pub fn priority(mut self, priority: Priority) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    /// Sets the `apns-collapse-id` header. Notifications sharing an ID replace
+    /// one another on the device instead of stacking up.
+    pub fn collapse_id(mut self, collapse_id: impl Into<String>) -> Self {
+        self.collapse_id = Some(collapse_id.into());
+        self
+    }
+
+    /// Sets the `thread-id` the device uses to group related notifications.
+    pub fn thread_id(mut self, thread_id: impl Into<String>) -> Self {
+        self.thread_id = Some(thread_id.into());
+        self
+    }
+
+    /// Sets how long APNS keeps trying to deliver the notification. A zero TTL
+    /// means it is delivered only if the device is reachable right away.
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    /// Overrides the content type, which defaults to `application/json;charset=utf-8`.
+    pub fn content_type(mut self, content_type: impl Into<String>) -> Self {
+        self.content_type = Some(content_type.into());
+        self
+    }
+
+    /// Checks the notification against the rules Apple enforces, which it
+    /// otherwise reports only by silently dropping the notification.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.push_type != "background" {
+            return Ok(());
+        }
+
+        if self.title.is_some() || self.body.is_some() {
+            return Err("a background notification cannot have an alert".to_string());
+        }
+        if self.badge.is_some() || self.sound.is_some() {
+            return Err("a background notification cannot set a badge or sound".to_string());
+        }
+        if !self.content_available {
+            return Err("a background notification must set content-available".to_string());
+        }
+        if self.priority != Priority::Normal {
+            return Err("a background notification must use normal priority".to_string());
+        }
+
+        Ok(())
+    }
+
+    /// Serializes the notification into the APNS JSON payload.
+    pub fn to_json(&self) -> String {
+        let alert = if self.title.is_some() || self.body.is_some() {
+            Some(Alert {
+                title: self.title.as_deref(),
+                body: self.body.as_deref(),
+            })
+        } else {
+            None
+        };
+
+        let payload = ApnsPayload {
+            aps: Aps {
+                alert,
+                badge: self.badge,
+                sound: self.sound.as_deref(),
+                content_available: self.content_available.then_some(1),
+                thread_id: self.thread_id.as_deref(),
+            },
+            data: &self.data,
+        };
+
+        serde_json::to_string(&payload).unwrap()
+    }
+}
+
+impl From<ApnsNotification> for NotificationRequest {
+    fn from(notification: ApnsNotification) -> Self {
+        let mut headers = HashMap::from([
+            ("apns-push-type".to_string(), notification.push_type.clone()),
+            (
+                "apns-priority".to_string(),
+                notification.priority.as_apns_priority().to_string(),
+            ),
+        ]);
+
+        if let Some(topic) = &notification.topic {
+            headers.insert("apns-topic".to_string(), topic.clone());
+        }
+
+        if let Some(collapse_id) = &notification.collapse_id {
+            headers.insert("apns-collapse-id".to_string(), collapse_id.clone());
+        }
+
+        if let Some(ttl) = notification.ttl {
+            headers.insert(
+                "apns-expiration".to_string(),
+                apns_expiration(Utc::now(), ttl),
+            );
+        }
+
+        NotificationRequest {
+            headers,
+            message: notification.to_json().into(),
+            content_type: notification
+                .content_type
+                .unwrap_or_else(|| Platform::Apple.default_content_type().unwrap().to_string()),
+            platform: Platform::Apple,
+        }
+    }
+}
+
+/// Converts a relative TTL into the `apns-expiration` header, which APNS
+/// expects as an absolute UNIX timestamp. `0` tells APNS not to store the
+/// notification at all, so non-positive TTLs map to it rather than to a time
+/// in the past.
+pub(crate) fn apns_expiration(now: DateTime<Utc>, ttl: Duration) -> String {
+    if ttl <= Duration::zero() {
+        return "0".to_string();
+    }
+
+    (now + ttl).timestamp().to_string()
+}
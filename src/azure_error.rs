@@ -0,0 +1,53 @@
+use serde::Deserialize;
+
+/// The machine-readable error Azure returns in the body of a failed request.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AzureError {
+    pub code: String,
+    pub message: String,
+}
+
+#[derive(Deserialize)]
+struct JsonEnvelope {
+    error: JsonError,
+}
+
+#[derive(Deserialize)]
+struct JsonError {
+    code: serde_json::Value,
+    #[serde(default)]
+    message: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct XmlError {
+    code: String,
+    #[serde(default)]
+    detail: String,
+}
+
+impl AzureError {
+    /// Parses either the JSON `{"error": {"code", "message"}}` envelope or the
+    /// XML `<Error><Code/><Detail/></Error>` document, returning `None` when
+    /// the body is neither.
+    pub(crate) fn parse(body: &str) -> Option<Self> {
+        if let Ok(envelope) = serde_json::from_str::<JsonEnvelope>(body) {
+            let code = match envelope.error.code {
+                serde_json::Value::String(code) => code,
+                code => code.to_string(),
+            };
+            return Some(Self {
+                code,
+                message: envelope.error.message,
+            });
+        }
+
+        quick_xml::de::from_str::<XmlError>(body)
+            .ok()
+            .map(|error| Self {
+                code: error.code,
+                message: error.detail,
+            })
+    }
+}
@@ -0,0 +1,76 @@
+use crate::notification_hub_client::NotificationRequest;
+use crate::platform::Platform;
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+
+/// Builds a Baidu Push notification, sent through Azure's `baidu` platform.
+#[derive(Clone, Debug, Default)]
+pub struct BaiduNotification {
+    title: Option<String>,
+    description: Option<String>,
+    custom_content: Map<String, Value>,
+    content_type: Option<String>,
+}
+
+#[derive(Serialize)]
+struct BaiduPayload<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    title: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    description: Option<&'a str>,
+    #[serde(skip_serializing_if = "Map::is_empty")]
+    custom_content: &'a Map<String, Value>,
+}
+
+impl BaiduNotification {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn title(mut self, title: impl Into<String>) -> Self {
+        self.title = Some(title.into());
+        self
+    }
+
+    pub fn description(mut self, description: impl Into<String>) -> Self {
+        self.description = Some(description.into());
+        self
+    }
+
+    /// Adds a key to the `custom_content` object delivered to the app.
+    pub fn custom_content(mut self, key: impl Into<String>, value: impl Into<Value>) -> Self {
+        self.custom_content.insert(key.into(), value.into());
+        self
+    }
+
+    /// Overrides the content type, which defaults to `application/x-www-form-urlencoded;charset=utf-8`.
+    pub fn content_type(mut self, content_type: impl Into<String>) -> Self {
+        self.content_type = Some(content_type.into());
+        self
+    }
+
+    /// Serializes the notification into the Baidu JSON payload.
+    pub fn to_json(&self) -> String {
+        let payload = BaiduPayload {
+            title: self.title.as_deref(),
+            description: self.description.as_deref(),
+            custom_content: &self.custom_content,
+        };
+
+        serde_json::to_string(&payload).unwrap()
+    }
+}
+
+impl From<BaiduNotification> for NotificationRequest {
+    fn from(notification: BaiduNotification) -> Self {
+        NotificationRequest {
+            headers: HashMap::new(),
+            message: notification.to_json().into(),
+            content_type: notification
+                .content_type
+                .unwrap_or_else(|| Platform::Baidu.default_content_type().unwrap().to_string()),
+            platform: Platform::Baidu,
+        }
+    }
+}
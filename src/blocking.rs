@@ -0,0 +1,137 @@
+//! A synchronous wrapper around the async client, for programs that do not run
+//! inside a Tokio runtime, such as CLI tools and scripts.
+//!
+//! Each client owns a current-thread runtime and blocks on it for every call,
+//! so its methods must not be called from within an async context.
+
+use crate::notification_hub_client::{
+    self, FromConnectionStringError, Installation, InstallationPatch, InstallationPathResponse,
+    NotificationRequest, NotificationRequestError, NotificationResponse,
+};
+use crate::notification_telemetry::NotificationTelemetry;
+use std::future::Future;
+use std::sync::Arc;
+use tokio::runtime::{Builder, Runtime};
+
+/// A blocking `NotificationHubClient`. Cloning it shares the underlying
+/// runtime and connection pool.
+#[derive(Clone)]
+pub struct NotificationHubClient {
+    inner: notification_hub_client::NotificationHubClient,
+    runtime: Arc<Runtime>,
+}
+
+impl NotificationHubClient {
+    pub fn from_connection_string(
+        connection_string: &str,
+        hub_name: &str,
+    ) -> Result<Self, FromConnectionStringError> {
+        let inner = notification_hub_client::NotificationHubClient::from_connection_string(
+            connection_string,
+            hub_name,
+        )?;
+        Ok(Self::new(inner))
+    }
+
+    /// Wraps an async client, keeping any configuration applied to it.
+    pub fn new(inner: notification_hub_client::NotificationHubClient) -> Self {
+        let runtime = Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .expect("the blocking client's runtime could not be started");
+
+        Self {
+            inner,
+            runtime: Arc::new(runtime),
+        }
+    }
+
+    fn block_on<F: Future>(&self, future: F) -> F::Output {
+        self.runtime.block_on(future)
+    }
+
+    pub fn get_installation(
+        &self,
+        installation_id: &str,
+    ) -> Result<Installation, NotificationRequestError> {
+        self.block_on(self.inner.get_installation(installation_id))
+    }
+
+    pub fn installation_exists(
+        &self,
+        installation_id: &str,
+    ) -> Result<bool, NotificationRequestError> {
+        self.block_on(self.inner.installation_exists(installation_id))
+    }
+
+    pub fn upsert_installation(
+        &self,
+        installation: Installation,
+    ) -> Result<InstallationPathResponse, NotificationRequestError> {
+        self.block_on(self.inner.upsert_installation(installation))
+    }
+
+    pub fn patch_installation(
+        &self,
+        installation_id: &str,
+        patches: Vec<InstallationPatch>,
+    ) -> Result<InstallationPathResponse, NotificationRequestError> {
+        self.block_on(self.inner.patch_installation(installation_id, patches))
+    }
+
+    pub fn delete_installation(
+        &self,
+        installation_id: &str,
+    ) -> Result<(), NotificationRequestError> {
+        self.block_on(self.inner.delete_installation(installation_id))
+    }
+
+    pub fn send_direct_notification(
+        &self,
+        request_message: NotificationRequest,
+        device_tokens: &[&str],
+    ) -> Result<NotificationResponse, NotificationRequestError> {
+        self.block_on(
+            self.inner
+                .send_direct_notification(request_message, device_tokens),
+        )
+    }
+
+    pub fn send_tagged_notification(
+        &self,
+        request_message: NotificationRequest,
+        tags: Vec<&str>,
+    ) -> Result<NotificationResponse, NotificationRequestError> {
+        self.block_on(self.inner.send_tagged_notification(request_message, tags))
+    }
+
+    pub fn send_tag_expression_notification(
+        &self,
+        request_message: NotificationRequest,
+        tag_expression: &str,
+    ) -> Result<NotificationResponse, NotificationRequestError> {
+        self.block_on(
+            self.inner
+                .send_tag_expression_notification(request_message, tag_expression),
+        )
+    }
+
+    pub fn send_notification(
+        &self,
+        request_message: NotificationRequest,
+        device_token: Option<&str>,
+        tag_expression: Option<&str>,
+    ) -> Result<NotificationResponse, NotificationRequestError> {
+        self.block_on(
+            self.inner
+                .send_notification(request_message, device_token, tag_expression),
+        )
+    }
+
+    pub fn get_notification_telemetry(
+        &self,
+        notification_id: &str,
+    ) -> Result<NotificationTelemetry, NotificationRequestError> {
+        self.block_on(self.inner.get_notification_telemetry(notification_id))
+    }
+}
@@ -0,0 +1,42 @@
+/// The Azure cloud a notification hub namespace lives in, which determines
+/// the DNS suffix of its endpoint.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum AzureCloud {
+    #[default]
+    Public,
+    UsGovernment,
+    China,
+    /// Any other cloud, given by its Service Bus DNS suffix.
+    Custom(String),
+}
+
+impl AzureCloud {
+    /// The DNS suffix shared by every Service Bus namespace in this cloud.
+    pub fn host_suffix(&self) -> &str {
+        match self {
+            AzureCloud::Public => "servicebus.windows.net",
+            AzureCloud::UsGovernment => "servicebus.usgovcloudapi.net",
+            AzureCloud::China => "servicebus.chinacloudapi.cn",
+            AzureCloud::Custom(suffix) => suffix.trim_start_matches('.'),
+        }
+    }
+
+    /// The endpoint of the given namespace, in the form used by connection strings.
+    pub fn endpoint(&self, namespace: &str) -> String {
+        format!("sb://{}.{}/", namespace, self.host_suffix())
+    }
+
+    /// Whether the endpoint's host belongs to this cloud.
+    pub fn matches_endpoint(&self, endpoint: &str) -> bool {
+        let host = endpoint
+            .split_once("://")
+            .map_or(endpoint, |(_, rest)| rest)
+            .split(['/', ':'])
+            .next()
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+
+        host.strip_suffix(&self.host_suffix().to_ascii_lowercase())
+            .is_some_and(|namespace| namespace.len() > 1 && namespace.ends_with('.'))
+    }
+}
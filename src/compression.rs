@@ -0,0 +1,65 @@
+//! Gzip request bodies and transparent response decompression, compiled only
+//! with the `compression` feature.
+
+use crate::notification_hub_client::{invalid_response_body, NotificationRequestError};
+use flate2::read::{DeflateDecoder, GzDecoder, ZlibDecoder};
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use hyper::body::Bytes;
+use hyper::header::{CONTENT_ENCODING, CONTENT_LENGTH};
+use hyper::{Body, Response};
+use std::io::{Read, Write};
+
+/// The `Accept-Encoding` value sent with every request
+pub(crate) const ACCEPT_ENCODING_VALUE: &str = "gzip, deflate";
+
+/// Compresses a request body for sending with `Content-Encoding: gzip`.
+pub(crate) fn gzip(body: &[u8]) -> Bytes {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder
+        .write_all(body)
+        .and_then(|_| encoder.finish())
+        .map(Bytes::from)
+        .expect("writing to a Vec cannot fail")
+}
+
+/// Decodes a `gzip` or `deflate` encoded response body, returning the
+/// response with the plain body and without its `Content-Encoding` and
+/// `Content-Length` headers. Other responses are returned unchanged.
+pub(crate) async fn decompress_response(
+    res: Response<Body>,
+) -> Result<Response<Body>, NotificationRequestError> {
+    let encoding = match res.headers().get(CONTENT_ENCODING) {
+        Some(value) => value
+            .to_str()
+            .unwrap_or_default()
+            .trim()
+            .to_ascii_lowercase(),
+        None => return Ok(res),
+    };
+    if encoding != "gzip" && encoding != "deflate" {
+        return Ok(res);
+    }
+
+    let (mut parts, body) = res.into_parts();
+    let body = hyper::body::to_bytes(body)
+        .await
+        .map_err(NotificationRequestError::HttpRequestError)?;
+    let decoded = if encoding == "gzip" {
+        decode(GzDecoder::new(&body[..]))
+    } else {
+        // Servers disagree on whether `deflate` means zlib-wrapped or raw data.
+        decode(ZlibDecoder::new(&body[..])).or_else(|_| decode(DeflateDecoder::new(&body[..])))
+    }
+    .map_err(|err| invalid_response_body(err, &body))?;
+
+    parts.headers.remove(CONTENT_ENCODING);
+    parts.headers.remove(CONTENT_LENGTH);
+    Ok(Response::from_parts(parts, Body::from(decoded)))
+}
+
+fn decode(mut decoder: impl Read) -> std::io::Result<Vec<u8>> {
+    let mut decoded = Vec::new();
+    decoder.read_to_end(&mut decoded)?;
+    Ok(decoded)
+}
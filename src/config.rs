@@ -0,0 +1,57 @@
+use crate::retry_policy::RetryPolicy;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// The tunable settings of a `NotificationHubClient`, applied with
+/// `NotificationHubClient::with_config`. Every field is optional and falls
+/// back to the client's default, so the struct can be loaded from a partial
+/// section of an application's own config file. Durations are written in
+/// milliseconds.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NotificationHubConfig {
+    #[serde(with = "option_duration_millis")]
+    pub timeout: Option<Duration>,
+    #[serde(with = "option_duration_millis")]
+    pub connect_timeout: Option<Duration>,
+    pub retry_policy: Option<RetryPolicy>,
+    /// The most requests to send per second
+    pub rate_limit: Option<u32>,
+    pub api_version: Option<String>,
+    pub user_agent: Option<String>,
+    pub http2: Option<bool>,
+}
+
+pub(crate) mod duration_millis {
+    use serde::{Deserialize, Deserializer, Serializer};
+    use std::time::Duration;
+
+    pub fn serialize<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u64(duration.as_millis().try_into().unwrap_or(u64::MAX))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+        Ok(Duration::from_millis(u64::deserialize(deserializer)?))
+    }
+}
+
+mod option_duration_millis {
+    use serde::{Deserialize, Deserializer, Serializer};
+    use std::time::Duration;
+
+    pub fn serialize<S: Serializer>(
+        duration: &Option<Duration>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match duration {
+            Some(duration) => super::duration_millis::serialize(duration, serializer),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<Duration>, D::Error> {
+        Ok(Option::<u64>::deserialize(deserializer)?.map(Duration::from_millis))
+    }
+}
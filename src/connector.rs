@@ -0,0 +1,122 @@
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use hyper::client::connect::{Connected, Connection};
+use hyper::service::Service;
+use hyper::Uri;
+use std::io;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+/// A connection returned by any connector, with its concrete stream type erased.
+pub(crate) trait Io: AsyncRead + AsyncWrite + Connection + Send + Unpin {}
+
+impl<T: AsyncRead + AsyncWrite + Connection + Send + Unpin> Io for T {}
+
+pub(crate) struct BoxedIo(Box<dyn Io>);
+
+impl Connection for BoxedIo {
+    fn connected(&self) -> Connected {
+        self.0.connected()
+    }
+}
+
+impl AsyncRead for BoxedIo {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut *self.0).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for BoxedIo {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut *self.0).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut *self.0).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut *self.0).poll_shutdown(cx)
+    }
+}
+
+/// Wraps any hyper connector behind a single type, so the client can hold
+/// either the built-in HTTPS connector or one supplied through `with_connector`.
+#[derive(Clone)]
+pub(crate) struct BoxedConnector {
+    connect: Arc<dyn Fn(Uri) -> BoxFuture<'static, Result<BoxedIo, BoxError>> + Send + Sync>,
+}
+
+impl BoxedConnector {
+    pub(crate) fn new<C>(connector: C) -> Self
+    where
+        C: Service<Uri> + Clone + Send + Sync + 'static,
+        C::Response: AsyncRead + AsyncWrite + Connection + Send + Unpin + 'static,
+        C::Future: Send + 'static,
+        C::Error: Into<BoxError>,
+    {
+        Self {
+            connect: Arc::new(move |uri| {
+                let mut connector = connector.clone();
+                async move {
+                    futures::future::poll_fn(|cx| connector.poll_ready(cx))
+                        .await
+                        .map_err(Into::into)?;
+                    let io = connector.call(uri).await.map_err(Into::into)?;
+                    Ok(BoxedIo(Box::new(io)))
+                }
+                .boxed()
+            }),
+        }
+    }
+
+    /// Fails connection attempts that take longer than `timeout` with a
+    /// `TimedOut` I/O error, as the built-in connector's connect timeout does.
+    pub(crate) fn with_connect_timeout(self, timeout: Option<Duration>) -> Self {
+        let timeout = match timeout {
+            Some(timeout) => timeout,
+            None => return self,
+        };
+
+        let connect = self.connect;
+        Self {
+            connect: Arc::new(move |uri| {
+                let connecting = connect(uri);
+                async move {
+                    match tokio::time::timeout(timeout, connecting).await {
+                        Ok(result) => result,
+                        Err(_) => Err(io::Error::from(io::ErrorKind::TimedOut).into()),
+                    }
+                }
+                .boxed()
+            }),
+        }
+    }
+}
+
+impl Service<Uri> for BoxedConnector {
+    type Response = BoxedIo;
+    type Error = BoxError;
+    type Future = BoxFuture<'static, Result<BoxedIo, BoxError>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, uri: Uri) -> Self::Future {
+        (self.connect)(uri)
+    }
+}
@@ -0,0 +1,45 @@
+//! TLS settings that skip certificate verification, compiled only with the
+//! `danger-accept-invalid-certs` feature.
+
+#[cfg(feature = "rustls")]
+pub(crate) fn rustls_config() -> rustls::ClientConfig {
+    use rustls::client::{ServerCertVerified, ServerCertVerifier};
+    use rustls::{Certificate, Error, ServerName};
+    use std::sync::Arc;
+    use std::time::SystemTime;
+
+    struct AcceptAnyCert;
+
+    impl ServerCertVerifier for AcceptAnyCert {
+        fn verify_server_cert(
+            &self,
+            _end_entity: &Certificate,
+            _intermediates: &[Certificate],
+            _server_name: &ServerName,
+            _scts: &mut dyn Iterator<Item = &[u8]>,
+            _ocsp_response: &[u8],
+            _now: SystemTime,
+        ) -> Result<ServerCertVerified, Error> {
+            Ok(ServerCertVerified::assertion())
+        }
+    }
+
+    let mut config = rustls::ClientConfig::builder()
+        .with_safe_defaults()
+        .with_root_certificates(rustls::RootCertStore::empty())
+        .with_no_client_auth();
+    config
+        .dangerous()
+        .set_certificate_verifier(Arc::new(AcceptAnyCert));
+    config
+}
+
+#[cfg(all(feature = "native-tls", not(feature = "rustls")))]
+pub(crate) fn native_tls_connector() -> tokio_native_tls::TlsConnector {
+    native_tls::TlsConnector::builder()
+        .danger_accept_invalid_certs(true)
+        .danger_accept_invalid_hostnames(true)
+        .build()
+        .expect("the TLS backend could not be initialized")
+        .into()
+}
@@ -0,0 +1,105 @@
+//! Snapshots of the exact requests sent to Azure and the responses received,
+//! compiled only with the `debug-capture` feature.
+
+use crate::notification_hub_client::{redact_signature, NotificationRequestError};
+use hyper::body::Bytes;
+use hyper::header::{HeaderMap, HeaderValue, AUTHORIZATION};
+use hyper::{Body, Method, Request, Response, StatusCode};
+
+/// One attempt at a request, passed to the callback registered with
+/// `NotificationHubClient::with_debug_capture`. Each retry is captured
+/// separately.
+#[derive(Clone, Debug)]
+pub struct DebugExchange {
+    pub request: CapturedRequest,
+    /// `None` when no response was received, e.g. after a timeout
+    pub response: Option<CapturedResponse>,
+}
+
+#[derive(Clone, Debug)]
+pub struct CapturedRequest {
+    pub method: Method,
+    /// The request URI, with any SAS signature masked
+    pub uri: String,
+    /// The headers as sent, with the signature in `Authorization` masked
+    pub headers: HeaderMap,
+    pub body: Bytes,
+}
+
+#[derive(Clone, Debug)]
+pub struct CapturedResponse {
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    /// The body after any decompression
+    pub body: Bytes,
+}
+
+/// Buffers the request body so it can be both captured and sent. Streamed
+/// bodies are read in full first.
+pub(crate) async fn capture_request(
+    request: Request<Body>,
+) -> Result<(Request<Body>, CapturedRequest), NotificationRequestError> {
+    let (parts, body) = request.into_parts();
+    let body = hyper::body::to_bytes(body)
+        .await
+        .map_err(NotificationRequestError::HttpRequestError)?;
+
+    let mut headers = parts.headers.clone();
+    if let Some(value) = headers.get(AUTHORIZATION) {
+        let redacted = value
+            .to_str()
+            .ok()
+            .and_then(|token| HeaderValue::from_str(&redact_authorization(token)).ok())
+            .unwrap_or_else(|| HeaderValue::from_static("REDACTED"));
+        headers.insert(AUTHORIZATION, redacted);
+    }
+
+    let captured = CapturedRequest {
+        method: parts.method.clone(),
+        uri: redact_signature(&parts.uri.to_string()),
+        headers,
+        body: body.clone(),
+    };
+    Ok((Request::from_parts(parts, Body::from(body)), captured))
+}
+
+/// Buffers the response body so it can be both captured and read by the caller.
+pub(crate) async fn capture_response(
+    res: Response<Body>,
+) -> Result<(Response<Body>, CapturedResponse), NotificationRequestError> {
+    let (parts, body) = res.into_parts();
+    let body = hyper::body::to_bytes(body)
+        .await
+        .map_err(NotificationRequestError::HttpRequestError)?;
+
+    let captured = CapturedResponse {
+        status: parts.status,
+        headers: parts.headers.clone(),
+        body: body.clone(),
+    };
+    Ok((Response::from_parts(parts, Body::from(body)), captured))
+}
+
+/// Masks everything after the scheme of an `Authorization` value, e.g.
+/// `Bearer REDACTED`, except that a `SharedAccessSignature sr=...&sig=...`
+/// token keeps every field but `sig`. A value without a scheme is masked whole.
+pub(crate) fn redact_authorization(token: &str) -> String {
+    match token.split_once(' ') {
+        Some((scheme, fields)) if scheme.eq_ignore_ascii_case("SharedAccessSignature") => {
+            let fields = fields
+                .split('&')
+                .map(|field| {
+                    if field.starts_with("sig=") {
+                        "sig=REDACTED"
+                    } else {
+                        field
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join("&");
+            format!("{} {}", scheme, fields)
+        }
+        Some((scheme, _)) => format!("{} REDACTED", scheme),
+        None => "REDACTED".to_string(),
+    }
+}
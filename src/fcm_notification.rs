@@ -0,0 +1,129 @@
+use crate::notification_hub_client::NotificationRequest;
+use crate::platform::Platform;
+use crate::priority::Priority;
+use chrono::Duration;
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// Builds a Firebase Cloud Messaging payload in the legacy format Azure
+/// sends through its `gcm` platform.
+#[derive(Clone, Debug, Default)]
+pub struct FcmNotification {
+    title: Option<String>,
+    body: Option<String>,
+    data: HashMap<String, String>,
+    priority: Option<Priority>,
+    collapse_key: Option<String>,
+    ttl: Option<Duration>,
+    content_type: Option<String>,
+}
+
+/// The longest `time_to_live` FCM accepts, four weeks in seconds.
+const MAX_TIME_TO_LIVE: i64 = 2_419_200;
+
+#[derive(Serialize)]
+struct FcmPayload<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    notification: Option<FcmMessage<'a>>,
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    data: &'a HashMap<String, String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    priority: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    collapse_key: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    time_to_live: Option<i64>,
+}
+
+#[derive(Serialize)]
+struct FcmMessage<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    title: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    body: Option<&'a str>,
+}
+
+impl FcmNotification {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn title(mut self, title: impl Into<String>) -> Self {
+        self.title = Some(title.into());
+        self
+    }
+
+    pub fn body(mut self, body: impl Into<String>) -> Self {
+        self.body = Some(body.into());
+        self
+    }
+
+    /// Adds a key to the `data` map delivered to the app.
+    pub fn data(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.data.insert(key.into(), value.into());
+        self
+    }
+
+    /// Sets the message `priority`, sent as `high` or `normal`.
+    pub fn priority(mut self, priority: Priority) -> Self {
+        self.priority = Some(priority);
+        self
+    }
+
+    /// Sets the `collapse_key`. While the device is offline, only the latest
+    /// message with a given key is kept for delivery.
+    pub fn collapse_key(mut self, collapse_key: impl Into<String>) -> Self {
+        self.collapse_key = Some(collapse_key.into());
+        self
+    }
+
+    /// Sets the `time_to_live`, how long FCM keeps the message while the device
+    /// is offline. It is sent in whole seconds, clamped to FCM's four week limit.
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    /// Overrides the content type, which defaults to `application/json;charset=utf-8`.
+    pub fn content_type(mut self, content_type: impl Into<String>) -> Self {
+        self.content_type = Some(content_type.into());
+        self
+    }
+
+    /// Serializes the notification into the FCM JSON payload.
+    pub fn to_json(&self) -> String {
+        let notification = if self.title.is_some() || self.body.is_some() {
+            Some(FcmMessage {
+                title: self.title.as_deref(),
+                body: self.body.as_deref(),
+            })
+        } else {
+            None
+        };
+
+        let payload = FcmPayload {
+            notification,
+            data: &self.data,
+            priority: self.priority.map(|priority| priority.as_fcm_priority()),
+            collapse_key: self.collapse_key.as_deref(),
+            time_to_live: self
+                .ttl
+                .map(|ttl| ttl.num_seconds().clamp(0, MAX_TIME_TO_LIVE)),
+        };
+
+        serde_json::to_string(&payload).unwrap()
+    }
+}
+
+impl From<FcmNotification> for NotificationRequest {
+    fn from(notification: FcmNotification) -> Self {
+        NotificationRequest {
+            headers: HashMap::new(),
+            message: notification.to_json().into(),
+            content_type: notification
+                .content_type
+                .unwrap_or_else(|| Platform::Fcm.default_content_type().unwrap().to_string()),
+            platform: Platform::Fcm,
+        }
+    }
+}
@@ -0,0 +1,140 @@
+use crate::notification_hub_client::NotificationRequest;
+use crate::platform::Platform;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
+
+/// Builds a Firebase Cloud Messaging HTTP v1 payload, sent through Azure's
+/// `fcmv1` platform.
+///
+/// The `android`, `apns`, and `webpush` override blocks are passed through
+/// as-is, so any field of the v1 schema can be set on them.
+#[derive(Clone, Debug, Default)]
+pub struct FcmV1Notification {
+    title: Option<String>,
+    body: Option<String>,
+    image: Option<String>,
+    data: HashMap<String, String>,
+    android: Option<Value>,
+    apns: Option<Value>,
+    webpush: Option<Value>,
+    content_type: Option<String>,
+}
+
+#[derive(Serialize)]
+struct FcmV1Payload<'a> {
+    message: FcmV1Message<'a>,
+}
+
+#[derive(Serialize)]
+struct FcmV1Message<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    notification: Option<FcmV1NotificationBlock<'a>>,
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    data: &'a HashMap<String, String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    android: Option<&'a Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    apns: Option<&'a Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    webpush: Option<&'a Value>,
+}
+
+#[derive(Serialize)]
+struct FcmV1NotificationBlock<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    title: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    body: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    image: Option<&'a str>,
+}
+
+impl FcmV1Notification {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn title(mut self, title: impl Into<String>) -> Self {
+        self.title = Some(title.into());
+        self
+    }
+
+    pub fn body(mut self, body: impl Into<String>) -> Self {
+        self.body = Some(body.into());
+        self
+    }
+
+    pub fn image(mut self, image: impl Into<String>) -> Self {
+        self.image = Some(image.into());
+        self
+    }
+
+    /// Adds a key to the `data` map delivered to the app.
+    pub fn data(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.data.insert(key.into(), value.into());
+        self
+    }
+
+    /// Sets the Android specific `android` override block.
+    pub fn android(mut self, android: Value) -> Self {
+        self.android = Some(android);
+        self
+    }
+
+    /// Sets the Apple specific `apns` override block.
+    pub fn apns(mut self, apns: Value) -> Self {
+        self.apns = Some(apns);
+        self
+    }
+
+    /// Sets the web push specific `webpush` override block.
+    pub fn webpush(mut self, webpush: Value) -> Self {
+        self.webpush = Some(webpush);
+        self
+    }
+
+    /// Overrides the content type, which defaults to `application/json;charset=utf-8`.
+    pub fn content_type(mut self, content_type: impl Into<String>) -> Self {
+        self.content_type = Some(content_type.into());
+        self
+    }
+
+    /// Serializes the notification into the FCM v1 `{"message": {...}}` envelope.
+    pub fn to_json(&self) -> String {
+        let notification = if self.title.is_some() || self.body.is_some() || self.image.is_some() {
+            Some(FcmV1NotificationBlock {
+                title: self.title.as_deref(),
+                body: self.body.as_deref(),
+                image: self.image.as_deref(),
+            })
+        } else {
+            None
+        };
+
+        let payload = FcmV1Payload {
+            message: FcmV1Message {
+                notification,
+                data: &self.data,
+                android: self.android.as_ref(),
+                apns: self.apns.as_ref(),
+                webpush: self.webpush.as_ref(),
+            },
+        };
+
+        serde_json::to_string(&payload).unwrap()
+    }
+}
+
+impl From<FcmV1Notification> for NotificationRequest {
+    fn from(notification: FcmV1Notification) -> Self {
+        NotificationRequest {
+            headers: HashMap::new(),
+            message: notification.to_json().into(),
+            content_type: notification
+                .content_type
+                .unwrap_or_else(|| Platform::FcmV1.default_content_type().unwrap().to_string()),
+            platform: Platform::FcmV1,
+        }
+    }
+}
@@ -0,0 +1,64 @@
+use crate::platform::Platform;
+use quick_xml::events::Event;
+use quick_xml::Reader;
+use std::collections::HashMap;
+
+/// Registration counts for a hub, as returned by `count_registrations`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct HubMetrics {
+    pub total_registrations: u64,
+    /// Native and template registrations, grouped by the platform they target
+    pub registrations_by_platform: HashMap<Platform, u64>,
+}
+
+impl HubMetrics {
+    /// Adds the registrations in one page of the registrations Atom feed, each
+    /// identified by the name of the description element inside its `content`.
+    pub(crate) fn count_feed_page(&mut self, xml: &[u8]) -> Result<(), quick_xml::Error> {
+        let mut reader = Reader::from_reader(xml);
+        let mut buf = Vec::new();
+        let mut in_content = false;
+
+        loop {
+            match reader.read_event_into(&mut buf)? {
+                Event::Start(element) if in_content => {
+                    self.count(platform_of(element.local_name().as_ref()));
+                    in_content = false;
+                }
+                Event::Empty(element) if in_content => {
+                    self.count(platform_of(element.local_name().as_ref()));
+                    in_content = false;
+                }
+                Event::Start(element) => in_content = element.local_name().as_ref() == b"content",
+                Event::End(_) => in_content = false,
+                Event::Eof => return Ok(()),
+                _ => {}
+            }
+            buf.clear();
+        }
+    }
+
+    fn count(&mut self, platform: Platform) {
+        self.total_registrations += 1;
+        *self.registrations_by_platform.entry(platform).or_default() += 1;
+    }
+}
+
+/// Maps a description element such as `AppleTemplateRegistrationDescription`
+/// to the platform it targets.
+fn platform_of(element: &[u8]) -> Platform {
+    let name = String::from_utf8_lossy(element);
+    let name = name
+        .strip_suffix("RegistrationDescription")
+        .unwrap_or(&name);
+    let name = name.strip_suffix("Template").unwrap_or(name);
+
+    match name {
+        "Apple" => Platform::Apple,
+        "Gcm" => Platform::Fcm,
+        "FcmV1" => Platform::FcmV1,
+        "Windows" => Platform::Windows,
+        "Baidu" => Platform::Baidu,
+        other => Platform::Other(other.to_string()),
+    }
+}
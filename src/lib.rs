@@ -1,17 +1,85 @@
+pub mod apns_notification;
+pub mod azure_error;
+pub mod baidu_notification;
+#[cfg(feature = "blocking")]
+pub mod blocking;
+pub mod cloud;
+#[cfg(feature = "compression")]
+mod compression;
+pub mod config;
+mod connector;
+#[cfg(feature = "danger-accept-invalid-certs")]
+mod danger;
+#[cfg(feature = "debug-capture")]
+pub mod debug_capture;
+pub mod fcm_notification;
+pub mod fcm_v1_notification;
+pub mod hub_metrics;
+pub mod namespace_client;
+pub mod notification_hub;
 pub mod notification_hub_client;
+pub mod notification_hub_job;
+pub mod notification_telemetry;
+pub mod platform;
+pub mod priority;
+mod rate_limiter;
+pub mod registration;
+pub mod request_metrics;
+pub mod retry_policy;
 pub mod sas_token_provider;
+pub mod tag_expression;
+pub mod token_provider;
+pub mod wns_notification;
 
 #[cfg(test)]
 mod tests {
 
-    use crate::notification_hub_client::{NotificationHubClient, NotificationRequest};
+    use crate::apns_notification::{apns_expiration, ApnsNotification};
+    use crate::azure_error::AzureError;
+    use crate::baidu_notification::BaiduNotification;
+    use crate::cloud::AzureCloud;
+    use crate::config::NotificationHubConfig;
+    use crate::fcm_notification::FcmNotification;
+    use crate::fcm_v1_notification::FcmV1Notification;
+    use crate::hub_metrics::HubMetrics;
+    use crate::namespace_client::NotificationHubDescription;
+    use crate::notification_hub::NotificationHub;
+    use crate::notification_hub_client::{
+        direct_batch_body, https_endpoint, installation_id_from_location, invalid_response_body,
+        notification_id_from_location, notification_response, read_expected_body, user_id_tag,
+        ConnectionStringParts, FromConnectionStringError, Installation, InstallationPatch,
+        NotificationHubClient, NotificationRequest, NotificationRequestError, RateLimitHint,
+        DEFAULT_API_VERSION, DEFAULT_USER_AGENT,
+    };
+    use crate::notification_hub_job::{JobStatus, JobType, NotificationHubJob};
+    use crate::notification_telemetry::{
+        NotificationOutcome, NotificationTelemetry, TestSendOutcome,
+    };
+    use crate::platform::Platform;
+    use crate::priority::Priority;
+    use crate::rate_limiter::RateLimiter;
+    use crate::registration::{AppleRegistrationDescription, RegistrationDescription};
+    use crate::retry_policy::{parse_retry_after, RetryPolicy};
+    use crate::sas_token_provider::{generate_sas_token, GenerateSasTokenError, SasTokenProvider};
+    use crate::tag_expression::{validate_tag, validate_tag_expression};
+    use crate::token_provider::{TokenProvider, TokenProviderError};
+    use crate::wns_notification::{WnsNotification, WnsType};
     use std::collections::HashMap;
+    use std::time::Duration;
 
     const MESSAGE_BODY: &str =
         r#"{"aps": { "alert": { "title": "My title", "body": "My body" } } }"#;
     const CONNECTION_STRING: &str = "<Connection-String>";
     const HUB_NAME: &str = "<-Hub-Name>";
     const DEVICE_TOKEN: &str = "<Device-Handle>";
+    const TEST_CONNECTION_STRING: &str =
+        "Endpoint=sb://example.servicebus.windows.net/;SharedAccessKeyName=name;SharedAccessKey=key";
+
+    /// A client for the hub `hub` of an example namespace. It only reaches a
+    /// server once given a connector such as `LocalConnector`.
+    fn test_client() -> NotificationHubClient {
+        NotificationHubClient::from_connection_string(TEST_CONNECTION_STRING, "hub").unwrap()
+    }
 
     #[tokio::test]
     async fn send_direct_notification() {
@@ -29,15 +97,2430 @@ mod tests {
 
         let notification_request = NotificationRequest {
             content_type: "application/json;charset=utf-8".to_string(),
-            message: MESSAGE_BODY.to_string(),
-            platform: "apple".to_string(),
-            headers: headers,
+            message: MESSAGE_BODY.into(),
+            platform: Platform::Apple,
+            headers,
         };
 
         let result = client
-            .send_direct_notification(notification_request, DEVICE_TOKEN)
+            .send_direct_notification(notification_request, &[DEVICE_TOKEN])
+            .await
+            .unwrap();
+        assert!(result.tracking_id.is_some_and(|id| !id.is_empty()));
+    }
+
+    #[test]
+    fn sas_token_expiry_uses_configured_ttl() {
+        let provider = SasTokenProvider::new("key-name".to_string(), "key-value".to_string())
+            .with_ttl(chrono::Duration::minutes(5));
+        let expected = (chrono::Utc::now() + chrono::Duration::minutes(5)).timestamp();
+
+        let token = provider.generate_sas_token("sb://example.net/").unwrap();
+        let expiry: i64 = token
+            .split('&')
+            .find_map(|part| part.strip_prefix("se="))
+            .unwrap()
+            .parse()
+            .unwrap();
+        assert!((expiry - expected).abs() <= 1);
+    }
+
+    #[test]
+    fn sas_token_is_deterministic_with_a_fixed_clock() {
+        let provider = SasTokenProvider::new("key-name".to_string(), "key-value".to_string())
+            .with_clock(|| {
+                "2021-01-01T00:00:00Z"
+                    .parse::<chrono::DateTime<chrono::Utc>>()
+                    .unwrap()
+            });
+
+        assert_eq!(
+            provider.generate_sas_token("sb://example.net/").unwrap(),
+            "SharedAccessSignature sr=sb%3A%2F%2Fexample.net%2F\
+             &sig=ztEAtClilnZGWIof%2BO9pOJzP%2BU6olfgYLM4audVPcc4%3D&se=1609462800&skn=key-name"
+        );
+    }
+
+    #[test]
+    fn standalone_sas_token_signs_any_resource() {
+        let token = generate_sas_token(
+            "key-name",
+            "key-value",
+            "https://Management.example.net/",
+            chrono::Duration::minutes(5),
+        )
+        .unwrap();
+
+        assert!(token
+            .starts_with("SharedAccessSignature sr=https%3A%2F%2Fmanagement.example.net%2F&sig="));
+        assert!(token.ends_with("&skn=key-name"));
+    }
+
+    #[test]
+    fn base64_keys_are_decoded_when_requested() {
+        let provider = SasTokenProvider::new("key-name".to_string(), "not base64!".to_string())
+            .with_base64_key(true);
+        assert!(matches!(
+            provider.generate_sas_token("sb://example.net/"),
+            Err(GenerateSasTokenError::DecodePrivateKeyError(_))
+        ));
+
+        let provider = SasTokenProvider::new("key-name".to_string(), "c2VjcmV0".to_string())
+            .with_base64_key(true);
+        assert!(provider.generate_sas_token("sb://example.net/").is_ok());
+    }
+
+    #[test]
+    fn error_sources_are_chained() {
+        use std::error::Error;
+
+        let provider = SasTokenProvider::new("key-name".to_string(), "not base64!".to_string())
+            .with_base64_key(true);
+        let err: NotificationRequestError = provider
+            .generate_sas_token("sb://example.net/")
+            .unwrap_err()
+            .into();
+
+        let sas_error = err.source().unwrap();
+        assert!(sas_error.is::<GenerateSasTokenError>());
+        let decode_error = sas_error.source().unwrap();
+        assert!(decode_error.is::<base64::DecodeError>());
+
+        // Each level describes only itself, so walking the chain repeats nothing.
+        assert_eq!(err.to_string(), "Failed to generate a SAS token");
+        assert!(!sas_error.to_string().contains(&decode_error.to_string()));
+    }
+
+    #[test]
+    fn fcm_v1_notification_wraps_message_with_overrides() {
+        let request: NotificationRequest = FcmV1Notification::new()
+            .title("My title")
+            .data("orderId", "42")
+            .android(serde_json::json!({ "priority": "high" }))
+            .into();
+
+        let payload: serde_json::Value = serde_json::from_slice(&request.message).unwrap();
+        assert_eq!(
+            payload,
+            serde_json::json!({
+                "message": {
+                    "notification": { "title": "My title" },
+                    "data": { "orderId": "42" },
+                    "android": { "priority": "high" }
+                }
+            })
+        );
+        assert_eq!(request.platform, "fcmv1");
+    }
+
+    #[test]
+    fn notification_telemetry_parses_outcome_counts() {
+        let xml = r#"<NotificationDetails xmlns="http://schemas.microsoft.com/netservices/2010/10/servicebus/connect" xmlns:i="http://www.w3.org/2001/XMLSchema-instance">
+                <NotificationId>abc-123</NotificationId>
+                <State>Completed</State>
+                <EnqueueTime>2021-01-01T00:00:00Z</EnqueueTime>
+                <TargetPlatforms>apple</TargetPlatforms>
+                <ApnsOutcomeCounts>
+                    <Outcome><Name>Success</Name><Count>3</Count></Outcome>
+                    <Outcome><Name>InvalidToken</Name><Count>1</Count></Outcome>
+                </ApnsOutcomeCounts>
+            </NotificationDetails>"#;
+
+        let telemetry = NotificationTelemetry::from_xml(xml.as_bytes()).unwrap();
+        assert_eq!(telemetry.notification_id, "abc-123");
+        assert_eq!(telemetry.state, "Completed");
+        assert!(telemetry.is_final());
+        assert_eq!(telemetry.apns_outcome_counts.len(), 2);
+        assert_eq!(telemetry.apns_outcome_counts[1].name, "InvalidToken");
+        assert_eq!(telemetry.apns_outcome_counts[1].count, 1);
+        assert!(telemetry.fcm_outcome_counts.is_empty());
+    }
+
+    #[test]
+    fn notification_telemetry_groups_outcomes_by_platform() {
+        let xml = r#"<NotificationDetails xmlns="http://schemas.microsoft.com/netservices/2010/10/servicebus/connect">
+                <NotificationId>abc-123</NotificationId>
+                <State>Completed</State>
+                <ApnsOutcomeCounts>
+                    <Outcome><Name>Success</Name><Count>3</Count></Outcome>
+                    <Outcome><Name>InvalidToken</Name><Count>1</Count></Outcome>
+                </ApnsOutcomeCounts>
+                <GcmOutcomeCounts>
+                    <Outcome><Name>Success</Name><Count>2</Count></Outcome>
+                    <Outcome><Name>SomethingNew</Name><Count>4</Count></Outcome>
+                </GcmOutcomeCounts>
+                <WnsOutcomeCounts>
+                    <Outcome><Name>ExpiredChannel</Name><Count>5</Count></Outcome>
+                </WnsOutcomeCounts>
+            </NotificationDetails>"#;
+
+        let telemetry = NotificationTelemetry::from_xml(xml.as_bytes()).unwrap();
+        let outcomes = telemetry.outcomes();
+        assert_eq!(outcomes.len(), 3);
+        assert_eq!(
+            outcomes[&Platform::Apple][&NotificationOutcome::InvalidToken],
+            1
+        );
+        assert_eq!(
+            outcomes[&Platform::Windows][&NotificationOutcome::ExpiredChannel],
+            5
+        );
+        assert_eq!(
+            outcomes[&Platform::Fcm][&NotificationOutcome::Other("SomethingNew".to_string())],
+            4
+        );
+        assert_eq!(telemetry.total(&NotificationOutcome::Success), 5);
+        assert!(telemetry.apns_outcome_counts[0].outcome().is_success());
+    }
+
+    #[test]
+    fn test_send_outcome_parses_registration_results() {
+        let xml = r#"<NotificationOutcome xmlns="http://schemas.microsoft.com/netservices/2010/10/servicebus/connect" xmlns:i="http://www.w3.org/2001/XMLSchema-instance">
+                <Success>1</Success>
+                <Failure>0</Failure>
+                <Results>
+                    <RegistrationResult>
+                        <ApplicationPlatform>apple</ApplicationPlatform>
+                        <PnsHandle>ABCDEF</PnsHandle>
+                        <RegistrationId>1234</RegistrationId>
+                        <Outcome>The Notification was successfully sent to the Push Notification System</Outcome>
+                    </RegistrationResult>
+                </Results>
+            </NotificationOutcome>"#;
+
+        let outcome = TestSendOutcome::from_xml(xml.as_bytes()).unwrap();
+        assert_eq!(outcome.success, 1);
+        assert_eq!(outcome.failure, 0);
+        assert_eq!(outcome.results.len(), 1);
+        assert_eq!(outcome.results[0].pns_handle, "ABCDEF");
+        assert_eq!(outcome.results[0].registration_id, "1234");
+    }
+
+    #[test]
+    fn registration_round_trips_through_atom_entry() {
+        let registration = RegistrationDescription::Apple(AppleRegistrationDescription {
+            tags: vec!["sports".to_string(), "news".to_string()],
+            device_token: "ABCDEF".to_string(),
+            ..Default::default()
+        });
+        assert_eq!(
+            registration.to_atom_entry().unwrap(),
+            concat!(
+                r#"<entry xmlns="http://www.w3.org/2005/Atom"><content type="application/xml">"#,
+                r#"<AppleRegistrationDescription xmlns="http://schemas.microsoft.com/netservices/2010/10/servicebus/connect">"#,
+                "<Tags>sports,news</Tags><DeviceToken>ABCDEF</DeviceToken>",
+                "</AppleRegistrationDescription></content></entry>"
+            )
+        );
+
+        let response = r#"<?xml version="1.0" encoding="utf-8"?>
+            <entry xmlns="http://www.w3.org/2005/Atom">
+                <id>https://example.servicebus.windows.net/hub/registrations/8155</id>
+                <title type="text">8155</title>
+                <content type="application/xml">
+                    <AppleRegistrationDescription xmlns:i="http://www.w3.org/2001/XMLSchema-instance" xmlns="http://schemas.microsoft.com/netservices/2010/10/servicebus/connect">
+                        <ETag>1</ETag>
+                        <ExpirationTime>9999-12-31T23:59:59.9999999Z</ExpirationTime>
+                        <RegistrationId>8155</RegistrationId>
+                        <Tags>sports, news</Tags>
+                        <DeviceToken>ABCDEF</DeviceToken>
+                    </AppleRegistrationDescription>
+                </content>
+            </entry>"#;
+        let parsed = RegistrationDescription::from_atom_entry(response.as_bytes()).unwrap();
+        assert_eq!(parsed.registration_id(), Some("8155"));
+        assert_eq!(parsed.tags(), ["sports", "news"]);
+        assert_eq!(parsed.pns_handle(), "ABCDEF");
+        assert_eq!(parsed.platform(), Platform::Apple);
+    }
+
+    #[test]
+    fn registrations_convert_into_installations() {
+        let installation = Installation::from(AppleRegistrationDescription {
+            registration_id: Some("8155".to_string()),
+            tags: vec!["sports".to_string()],
+            device_token: "ABCDEF".to_string(),
+            ..Default::default()
+        });
+        assert_eq!(installation.installation_id, "8155");
+        assert_eq!(installation.platform, Platform::Apple);
+        assert_eq!(installation.push_channel, "ABCDEF");
+        assert_eq!(installation.tags, ["sports"]);
+
+        let installation: Installation = crate::registration::FcmRegistrationDescription {
+            fcm_registration_id: "fcm-token".to_string(),
+            ..Default::default()
+        }
+        .into();
+        assert_eq!(installation.platform, Platform::Fcm);
+        assert_eq!(installation.push_channel, "fcm-token");
+        assert!(installation.installation_id.is_empty());
+    }
+
+    #[test]
+    fn retry_backoff_grows_exponentially_up_to_max_delay() {
+        let policy = RetryPolicy::new(5, Duration::from_millis(100))
+            .with_max_delay(Duration::from_millis(350));
+
+        assert_eq!(policy.backoff(0), Duration::from_millis(100));
+        assert_eq!(policy.backoff(1), Duration::from_millis(200));
+        assert_eq!(policy.backoff(2), Duration::from_millis(350));
+    }
+
+    #[tokio::test]
+    async fn only_idempotent_requests_are_retried_when_unavailable() {
+        let policy = RetryPolicy::new(3, Duration::from_millis(1));
+        let (client, requests) = recording_client(|_| response(503, ""));
+        let client = client.with_retry_policy(policy.clone());
+
+        let result = client
+            .send_direct_notification(
+                NotificationRequest::new(Platform::Apple, "application/json", "{}"),
+                &["token"],
+            )
+            .await;
+        assert!(matches!(
+            result,
+            Err(NotificationRequestError::InvalidHttpResponse { status, .. }) if status == 503
+        ));
+        assert_eq!(requests.lock().unwrap().len(), 1);
+
+        assert!(client.get_installation("installation").await.is_err());
+        assert_eq!(requests.lock().unwrap().len(), 4);
+
+        let (client, requests) = recording_client(|_| response(429, ""));
+        let client = client.with_retry_policy(policy);
+        assert!(client
+            .send_direct_notification(
+                NotificationRequest::new(Platform::Apple, "application/json", "{}"),
+                &["token"],
+            )
+            .await
+            .is_err());
+        assert_eq!(requests.lock().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn retry_after_accepts_seconds_or_http_date() {
+        assert_eq!(parse_retry_after("7"), Some(Duration::from_secs(7)));
+        assert_eq!(
+            parse_retry_after("Wed, 21 Oct 2015 07:28:00 GMT"),
+            Some(Duration::ZERO)
+        );
+        assert_eq!(parse_retry_after("soon"), None);
+    }
+
+    #[test]
+    fn apns_notification_builds_apple_request() {
+        let request: NotificationRequest = ApnsNotification::new()
+            .title("My title")
+            .body("My body")
+            .badge(3)
+            .data("orderId", 42)
+            .topic("com.example.app")
+            .into();
+
+        let payload: serde_json::Value = serde_json::from_slice(&request.message).unwrap();
+        assert_eq!(
+            payload,
+            serde_json::json!({
+                "aps": { "alert": { "title": "My title", "body": "My body" }, "badge": 3 },
+                "orderId": 42
+            })
+        );
+        assert_eq!(request.platform, "apple");
+        assert_eq!(request.headers["apns-push-type"], "alert");
+        assert_eq!(request.headers["apns-priority"], "10");
+        assert_eq!(request.headers["apns-topic"], "com.example.app");
+    }
+
+    #[test]
+    fn apns_background_notifications_are_silent() {
+        let mut data = serde_json::Map::new();
+        data.insert("sync".to_string(), serde_json::json!(true));
+        let notification = ApnsNotification::background(data);
+        assert!(notification.validate().is_ok());
+
+        let request: NotificationRequest = notification.clone().into();
+        let payload: serde_json::Value = serde_json::from_slice(&request.message).unwrap();
+        assert_eq!(
+            payload,
+            serde_json::json!({ "aps": { "content-available": 1 }, "sync": true })
+        );
+        assert_eq!(request.headers["apns-push-type"], "background");
+        assert_eq!(request.headers["apns-priority"], "5");
+
+        assert!(notification.clone().body("Hello").validate().is_err());
+        assert!(notification.priority(Priority::High).validate().is_err());
+    }
+
+    #[test]
+    fn priority_maps_to_each_platform() {
+        let apns: NotificationRequest = ApnsNotification::new()
+            .body("Hello")
+            .priority(Priority::Normal)
+            .into();
+        assert_eq!(apns.headers["apns-priority"], "5");
+
+        let fcm: NotificationRequest = FcmNotification::new()
+            .body("Hello")
+            .priority(Priority::Normal)
+            .into();
+        let payload: serde_json::Value = serde_json::from_slice(&fcm.message).unwrap();
+        assert_eq!(payload["priority"], "normal");
+    }
+
+    #[test]
+    fn collapse_identifiers_map_to_each_platform() {
+        let apns: NotificationRequest = ApnsNotification::new()
+            .body("Score: 2-1")
+            .collapse_id("match-42")
+            .thread_id("match-42")
+            .into();
+        let payload: serde_json::Value = serde_json::from_slice(&apns.message).unwrap();
+        assert_eq!(apns.headers["apns-collapse-id"], "match-42");
+        assert_eq!(payload["aps"]["thread-id"], "match-42");
+
+        let fcm: NotificationRequest = FcmNotification::new()
+            .body("Score: 2-1")
+            .collapse_key("match-42")
+            .into();
+        let payload: serde_json::Value = serde_json::from_slice(&fcm.message).unwrap();
+        assert_eq!(payload["collapse_key"], "match-42");
+    }
+
+    #[test]
+    fn ttl_maps_to_apns_expiration_and_fcm_time_to_live() {
+        let now = chrono::Utc::now();
+        assert_eq!(
+            apns_expiration(now, chrono::Duration::hours(1)),
+            (now.timestamp() + 3600).to_string()
+        );
+        assert_eq!(apns_expiration(now, chrono::Duration::zero()), "0");
+        assert_eq!(apns_expiration(now, chrono::Duration::seconds(-5)), "0");
+
+        let apns: NotificationRequest = ApnsNotification::new()
+            .body("Hello")
+            .ttl(chrono::Duration::hours(1))
+            .into();
+        let expiration: i64 = apns.headers["apns-expiration"].parse().unwrap();
+        assert!(expiration > chrono::Utc::now().timestamp());
+
+        let fcm: NotificationRequest = FcmNotification::new()
+            .body("Hello")
+            .ttl(chrono::Duration::minutes(10))
+            .into();
+        let payload: serde_json::Value = serde_json::from_slice(&fcm.message).unwrap();
+        assert_eq!(payload["time_to_live"], 600);
+
+        let fcm: NotificationRequest = FcmNotification::new()
+            .ttl(chrono::Duration::weeks(8))
+            .into();
+        let payload: serde_json::Value = serde_json::from_slice(&fcm.message).unwrap();
+        assert_eq!(payload["time_to_live"], 2_419_200);
+    }
+
+    #[test]
+    fn notification_hub_jobs_round_trip_through_atom_entries() {
+        let job = NotificationHubJob::import(
+            JobType::ImportUpsertInstallations,
+            "https://example.blob.core.windows.net/input/devices.txt?sig=a",
+            "https://example.blob.core.windows.net/output?sig=b",
+        );
+        assert_eq!(
+            job.to_atom_entry().unwrap(),
+            concat!(
+                r#"<entry xmlns="http://www.w3.org/2005/Atom"><content type="application/xml">"#,
+                r#"<NotificationHubJob xmlns="http://schemas.microsoft.com/netservices/2010/10/servicebus/connect">"#,
+                "<Type>ImportUpsertInstallations</Type>",
+                "<OutputContainerUri>https://example.blob.core.windows.net/output?sig=b</OutputContainerUri>",
+                "<ImportFileUri>https://example.blob.core.windows.net/input/devices.txt?sig=a</ImportFileUri>",
+                "</NotificationHubJob></content></entry>"
+            )
+        );
+
+        let xml = r#"<entry xmlns="http://www.w3.org/2005/Atom">
+            <content type="application/xml">
+                <NotificationHubJob xmlns="http://schemas.microsoft.com/netservices/2010/10/servicebus/connect" xmlns:i="http://www.w3.org/2001/XMLSchema-instance">
+                    <JobId>42</JobId>
+                    <Progress>100.00</Progress>
+                    <Type>ExportRegistrations</Type>
+                    <Status>Completed</Status>
+                    <OutputContainerUri>https://example.blob.core.windows.net/output</OutputContainerUri>
+                    <OutputProperties xmlns:d3p1="http://schemas.microsoft.com/2003/10/Serialization/Arrays">
+                        <d3p1:KeyValueOfstringstring>
+                            <d3p1:Key>OutputFilePath</d3p1:Key>
+                            <d3p1:Value>output/42/Output.txt</d3p1:Value>
+                        </d3p1:KeyValueOfstringstring>
+                    </OutputProperties>
+                    <CreatedAt>2024-01-01T00:00:00Z</CreatedAt>
+                </NotificationHubJob>
+            </content>
+        </entry>"#;
+        let job = NotificationHubJob::from_atom_entry(xml.as_bytes()).unwrap();
+        assert_eq!(job.job_id.as_deref(), Some("42"));
+        assert_eq!(job.job_type, JobType::ExportRegistrations);
+        assert_eq!(job.status, Some(JobStatus::Completed));
+        assert!(job.is_finished());
+        assert_eq!(
+            job.output_properties["OutputFilePath"],
+            "output/42/Output.txt"
+        );
+    }
+
+    #[test]
+    fn notification_hub_descriptions_are_parsed_from_the_namespace_feed() {
+        let feed = r#"<feed xmlns="http://www.w3.org/2005/Atom">
+                <title type="text">NotificationHubs</title>
+                <entry>
+                    <title type="text">hub-one</title>
+                    <content type="application/xml">
+                        <NotificationHubDescription xmlns="http://schemas.microsoft.com/netservices/2010/10/servicebus/connect" xmlns:i="http://www.w3.org/2001/XMLSchema-instance">
+                            <RegistrationTtl>P39D</RegistrationTtl>
+                            <ApnsCredential><Properties><Property><Name>Endpoint</Name><Value>x</Value></Property></Properties></ApnsCredential>
+                            <FcmV1Credential><Properties/></FcmV1Credential>
+                        </NotificationHubDescription>
+                    </content>
+                </entry>
+                <entry>
+                    <title type="text">hub-two</title>
+                    <content type="application/xml">
+                        <NotificationHubDescription xmlns="http://schemas.microsoft.com/netservices/2010/10/servicebus/connect"/>
+                    </content>
+                </entry>
+            </feed>"#;
+
+        let hubs = NotificationHubDescription::from_atom_feed(feed.as_bytes()).unwrap();
+        assert_eq!(hubs.len(), 2);
+        assert_eq!(hubs[0].name, "hub-one");
+        assert_eq!(hubs[0].registration_ttl.as_deref(), Some("P39D"));
+        assert_eq!(hubs[0].platforms, [Platform::Apple, Platform::FcmV1]);
+        assert_eq!(hubs[1].name, "hub-two");
+        assert!(hubs[1].platforms.is_empty());
+    }
+
+    #[test]
+    fn hub_metrics_count_registrations_per_platform() {
+        let page = r#"<feed xmlns="http://www.w3.org/2005/Atom">
+            <entry><content type="application/xml"><AppleRegistrationDescription xmlns="http://schemas.microsoft.com/netservices/2010/10/servicebus/connect"><DeviceToken>abc</DeviceToken></AppleRegistrationDescription></content></entry>
+            <entry><content type="application/xml"><AppleTemplateRegistrationDescription xmlns="http://schemas.microsoft.com/netservices/2010/10/servicebus/connect"><DeviceToken>def</DeviceToken></AppleTemplateRegistrationDescription></content></entry>
+            <entry><content type="application/xml"><GcmRegistrationDescription xmlns="http://schemas.microsoft.com/netservices/2010/10/servicebus/connect"/></content></entry>
+        </feed>"#;
+
+        let mut metrics = HubMetrics::default();
+        metrics.count_feed_page(page.as_bytes()).unwrap();
+        metrics
+            .count_feed_page(
+                b"<feed><entry><content><MpnsRegistrationDescription/></content></entry></feed>",
+            )
+            .unwrap();
+
+        assert_eq!(metrics.total_registrations, 4);
+        assert_eq!(metrics.registrations_by_platform[&Platform::Apple], 2);
+        assert_eq!(metrics.registrations_by_platform[&Platform::Fcm], 1);
+        assert_eq!(
+            metrics.registrations_by_platform[&Platform::Other("Mpns".to_string())],
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn registrations_are_counted_across_every_page() {
+        let (client, requests) = recording_client(|request| {
+            if request.uri.query().unwrap().contains("continuationtoken=") {
+                return response(
+                    200,
+                    "<feed><entry><content><GcmRegistrationDescription/></content></entry></feed>",
+                );
+            }
+            let mut res = response(
+                200,
+                "<feed><entry><content><AppleRegistrationDescription/></content></entry>\
+                 <entry><content><AppleRegistrationDescription/></content></entry></feed>",
+            );
+            res.headers_mut().insert(
+                "x-ms-continuationtoken",
+                hyper::header::HeaderValue::from_static("page 2"),
+            );
+            res
+        });
+
+        let metrics = client.count_registrations().await.unwrap();
+        assert_eq!(metrics.total_registrations, 3);
+        assert_eq!(metrics.registrations_by_platform[&Platform::Apple], 2);
+        assert_eq!(metrics.registrations_by_platform[&Platform::Fcm], 1);
+
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[0].uri.path(), "/hub/registrations");
+        assert!(!requests[0]
+            .uri
+            .query()
+            .unwrap()
+            .contains("continuationtoken"));
+        assert!(requests[1]
+            .uri
+            .query()
+            .unwrap()
+            .contains("continuationtoken=page%202"));
+    }
+
+    #[test]
+    fn fcm_notification_builds_gcm_request() {
+        let request: NotificationRequest = FcmNotification::new()
+            .title("My title")
+            .body("My body")
+            .data("orderId", "42")
+            .priority(Priority::High)
+            .into();
+
+        let payload: serde_json::Value = serde_json::from_slice(&request.message).unwrap();
+        assert_eq!(
+            payload,
+            serde_json::json!({
+                "notification": { "title": "My title", "body": "My body" },
+                "data": { "orderId": "42" },
+                "priority": "high"
+            })
+        );
+        assert_eq!(request.platform, "gcm");
+        assert_eq!(request.content_type, "application/json;charset=utf-8");
+    }
+
+    #[test]
+    fn baidu_notification_builds_baidu_request() {
+        let request: NotificationRequest = BaiduNotification::new()
+            .title("My title")
+            .description("My description")
+            .custom_content("orderId", 42)
+            .into();
+
+        let payload: serde_json::Value = serde_json::from_slice(&request.message).unwrap();
+        assert_eq!(
+            payload,
+            serde_json::json!({
+                "title": "My title",
+                "description": "My description",
+                "custom_content": { "orderId": 42 }
+            })
+        );
+        assert_eq!(request.platform, Platform::Baidu);
+    }
+
+    #[test]
+    fn connection_string_keys_are_matched_exactly_in_any_order() {
+        let parts = ConnectionStringParts::parse(
+            "SharedAccessKey=c2VjcmV0PQ==;endpoint=sb://example.servicebus.windows.net/;\
+             SharedAccessKeyName=DefaultFullSharedAccessSignature;EntityPath=my-hub;",
+        )
+        .unwrap();
+
+        assert_eq!(parts.endpoint, "sb://example.servicebus.windows.net/");
+        assert_eq!(parts.sas_key_name, "DefaultFullSharedAccessSignature");
+        assert_eq!(parts.sas_key_value, "c2VjcmV0PQ==");
+        assert_eq!(parts.entity_path, Some("my-hub"));
+    }
+
+    #[test]
+    fn connection_string_endpoint_must_be_a_url() {
+        assert!(matches!(
+            NotificationHubClient::from_connection_string(
+                "Endpoint=example servicebus;SharedAccessKeyName=name;SharedAccessKey=key",
+                "hub",
+            ),
+            Err(FromConnectionStringError::InvalidEndpoint(endpoint)) if endpoint == "example servicebus"
+        ));
+        assert!(matches!(
+            NotificationHubClient::from_connection_string(
+                "Endpoint=sb://;SharedAccessKeyName=name;SharedAccessKey=key",
+                "hub",
+            ),
+            Err(FromConnectionStringError::InvalidEndpoint(_))
+        ));
+    }
+
+    #[test]
+    fn installation_is_expired_once_expiration_time_passes() {
+        let mut installation: Installation = serde_json::from_value(serde_json::json!({
+            "installationId": "installation-1",
+            "userId": "user-1",
+            "lastActiveOn": "2021-01-01T00:00:00Z",
+            "expirationTime": "2999-01-01T00:00:00Z",
+            "lastUpdate": "2021-01-01T00:00:00Z",
+            "platform": "apns",
+            "pushChannel": "ABCDEF",
+            "expiredPushChannel": false,
+            "tags": [],
+            "templates": {}
+        }))
+        .unwrap();
+        assert_eq!(installation.platform, Platform::Apple);
+        assert_eq!(installation.etag, None);
+        installation.etag = Some("\"1\"".to_string());
+        let json = serde_json::to_value(&installation).unwrap();
+        assert_eq!(json["platform"], "apns");
+        assert!(json.get("etag").is_none());
+        assert!(!installation.is_expired());
+
+        installation.expiration_time = Some("2021-01-01T00:00:00Z".parse().unwrap());
+        assert!(installation.is_expired());
+    }
+
+    #[test]
+    fn partial_installations_deserialize_with_defaults() {
+        let installation: Installation = serde_json::from_value(serde_json::json!({
+            "installationId": "installation-1",
+            "platform": "fcmv1",
+            "pushChannel": "token",
+            "templates": { "greeting": { "body": "{\"message\":\"$(text)\"}" } }
+        }))
+        .unwrap();
+
+        assert_eq!(installation.user_id, None);
+        assert!(!installation.expired_push_channel);
+        assert!(installation.tags.is_empty());
+        assert!(installation.templates["greeting"].headers.is_empty());
+        assert!(serde_json::to_value(&installation)
+            .unwrap()
+            .get("userId")
+            .is_none());
+    }
+
+    #[test]
+    fn installation_timestamps_tolerate_azure_formats() {
+        let installation: Installation = serde_json::from_value(serde_json::json!({
+            "installationId": "installation-1",
+            "userId": "user-1",
+            "lastActiveOn": "2021-01-01T00:00:00.1234567Z",
+            "expirationTime": "9999-12-31T23:59:59.9999999",
+            "lastUpdate": "",
+            "platform": "apns",
+            "pushChannel": "ABCDEF",
+            "expiredPushChannel": false,
+            "tags": [],
+            "templates": {}
+        }))
+        .unwrap();
+
+        assert_eq!(
+            installation.last_active_on.unwrap().timestamp(),
+            1_609_459_200
+        );
+        assert_eq!(
+            installation.expiration_time.unwrap().to_rfc3339(),
+            "9999-12-31T23:59:59.999999900+00:00"
+        );
+        assert_eq!(installation.last_update, None);
+
+        let json = serde_json::to_value(&installation).unwrap();
+        assert_eq!(json["lastActiveOn"], "2021-01-01T00:00:00.123456700Z");
+        assert!(json.get("lastUpdate").is_none());
+    }
+
+    #[test]
+    fn installation_patches_serialize_as_json_patch() {
+        let patches = vec![
+            InstallationPatch::add("/tags", "sports"),
+            InstallationPatch::remove("/tags/news"),
+        ];
+
+        assert_eq!(
+            serde_json::to_value(&patches).unwrap(),
+            serde_json::json!([
+                { "op": "add", "path": "/tags", "value": "sports" },
+                { "op": "remove", "path": "/tags/news" }
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn tag_updates_are_sent_as_json_patch() {
+        let (client, requests) = recording_client(|_| response(200, ""));
+
+        client
+            .replace_tags("installation", vec!["sports", "news"])
             .await
             .unwrap();
-        assert!(result.tracking_id.len() > 0);
+        client
+            .add_tags("installation", vec!["sports", "news"])
+            .await
+            .unwrap();
+        assert!(matches!(
+            client.add_tags("installation", vec![]).await,
+            Err(NotificationRequestError::NoTags)
+        ));
+
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 2);
+        for request in requests.iter() {
+            assert_eq!(request.method, hyper::Method::PATCH);
+            assert_eq!(request.uri.path(), "/hub/installations/installation");
+            assert_eq!(
+                request.headers[hyper::header::CONTENT_TYPE],
+                "application/json"
+            );
+        }
+        assert_eq!(
+            serde_json::from_slice::<serde_json::Value>(&requests[0].body).unwrap(),
+            serde_json::json!([
+                { "op": "replace", "path": "/tags", "value": "[\"sports\",\"news\"]" }
+            ])
+        );
+        assert_eq!(
+            serde_json::from_slice::<serde_json::Value>(&requests[1].body).unwrap(),
+            serde_json::json!([
+                { "op": "add", "path": "/tags", "value": "sports" },
+                { "op": "add", "path": "/tags", "value": "news" }
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn send_notification_rejects_device_handle_with_tag_expression() {
+        let result = test_client()
+            .send_notification(NotificationRequest::default(), Some("handle"), Some("tag"))
+            .await;
+        assert!(matches!(
+            result,
+            Err(NotificationRequestError::InvalidAudience)
+        ));
+    }
+
+    #[test]
+    fn wns_toast_builds_escaped_xml_request() {
+        let request: NotificationRequest = WnsNotification::toast()
+            .text("Sale & more")
+            .text("<50% off>")
+            .into();
+
+        assert_eq!(
+            request.message_text(),
+            concat!(
+                r#"<toast><visual><binding template="ToastGeneric">"#,
+                "<text>Sale &amp; more</text><text>&lt;50% off&gt;</text>",
+                "</binding></visual></toast>"
+            )
+        );
+        assert_eq!(request.platform, "windows");
+        assert_eq!(request.content_type, "application/xml");
+        assert_eq!(request.headers["X-WNS-Type"], "wns/toast");
+    }
+
+    #[tokio::test]
+    async fn empty_response_bodies_are_reported_as_empty() {
+        let result = read_expected_body(hyper::Response::new(hyper::Body::from(" \r\n"))).await;
+        assert!(matches!(
+            result,
+            Err(NotificationRequestError::EmptyResponse)
+        ));
+
+        let body = read_expected_body(hyper::Response::new(hyper::Body::from("{}")))
+            .await
+            .unwrap();
+        assert_eq!(&body[..], b"{}");
+    }
+
+    #[test]
+    fn installation_id_is_parsed_from_content_location() {
+        assert_eq!(
+            installation_id_from_location(
+                "https://example.servicebus.windows.net/hub/installations/user%201%2Fdevice?api-version=2017-04"
+            )
+            .as_deref(),
+            Some("user 1/device")
+        );
+        assert_eq!(
+            installation_id_from_location("https://example.servicebus.windows.net/hub/messages/42"),
+            None
+        );
+        assert_eq!(installation_id_from_location(""), None);
+    }
+
+    #[test]
+    fn endpoints_are_normalized_to_https() {
+        assert_eq!(
+            https_endpoint("sb://example.servicebus.windows.net/"),
+            "https://example.servicebus.windows.net"
+        );
+        assert_eq!(
+            https_endpoint("https://example.servicebus.windows.net"),
+            "https://example.servicebus.windows.net"
+        );
+        assert_eq!(
+            https_endpoint("sb://localhost:5671/"),
+            "https://localhost:5671"
+        );
+        assert_eq!(
+            https_endpoint("https://localhost:8443/"),
+            "https://localhost:8443"
+        );
+        assert_eq!(
+            https_endpoint("example.servicebus.windows.net"),
+            "https://example.servicebus.windows.net"
+        );
+    }
+
+    #[test]
+    fn platform_constructors_fill_in_defaults() {
+        let request = NotificationRequest::apple(MESSAGE_BODY);
+        assert_eq!(request.platform, Platform::Apple);
+        assert_eq!(request.content_type, "application/json;charset=utf-8");
+        assert_eq!(request.headers["apns-push-type"], "alert");
+        assert_eq!(request.headers["apns-priority"], "10");
+
+        let request = NotificationRequest::fcm(r#"{"data":{}}"#);
+        assert_eq!(request.platform, Platform::Fcm);
+        assert!(request.headers.is_empty());
+
+        let request = NotificationRequest::windows("<toast/>", WnsType::Toast);
+        assert_eq!(request.content_type, "application/xml");
+        assert_eq!(request.headers["X-WNS-Type"], "wns/toast");
+
+        let request = NotificationRequest::windows("raw", WnsType::Raw);
+        assert_eq!(request.content_type, "application/octet-stream");
+        assert_eq!(request.headers["X-WNS-Type"], "wns/raw");
+    }
+
+    #[test]
+    fn tracking_id_replaces_any_existing_header() {
+        let mut request = NotificationRequest::new(Platform::Apple, "application/json", "{}");
+        request
+            .headers
+            .insert("TrackingId".to_string(), "old".to_string());
+
+        let request = request.with_tracking_id("send-1");
+        assert_eq!(request.tracking_id(), Some("send-1"));
+        assert_eq!(request.headers.len(), 1);
+    }
+
+    #[test]
+    fn user_id_tags_are_valid_tag_expressions() {
+        let tag = user_id_tag("user-1@example.com");
+        assert_eq!(tag, "$UserId:{user-1@example.com}");
+        assert!(crate::tag_expression::validate_tag(&tag).is_ok());
+        assert!(crate::tag_expression::validate_tag_expression(&tag).is_ok());
+    }
+
+    #[test]
+    fn notification_response_keeps_every_header() {
+        let res = hyper::Response::builder()
+            .status(201)
+            .header(
+                "Location",
+                "https://example.servicebus.windows.net/hub/messages/42?api-version=2017-04",
+            )
+            .header("TrackingId", "tracking")
+            .header("x-ms-request-id", "request")
+            .body(hyper::Body::empty())
+            .unwrap();
+
+        let response = notification_response(&res);
+        assert_eq!(response.notification_id.as_deref(), Some("42"));
+        assert_eq!(response.tracking_id.as_deref(), Some("tracking"));
+        assert_eq!(response.headers["x-ms-request-id"], "request");
+        assert_eq!(response.headers.len(), 3);
+    }
+
+    #[test]
+    fn builders_default_content_types_can_be_overridden() {
+        let raw: NotificationRequest = WnsNotification::raw("payload").into();
+        assert_eq!(raw.content_type, "application/octet-stream");
+        let fcm: NotificationRequest = FcmNotification::new().body("Hello").into();
+        assert_eq!(fcm.content_type, "application/json;charset=utf-8");
+
+        let apns: NotificationRequest = ApnsNotification::new()
+            .body("Hello")
+            .content_type("application/json")
+            .into();
+        assert_eq!(apns.content_type, "application/json");
+        let toast: NotificationRequest = WnsNotification::toast().content_type("text/xml").into();
+        assert_eq!(toast.content_type, "text/xml");
+        assert_eq!(
+            Platform::Other("mpns".to_string()).default_content_type(),
+            None
+        );
+    }
+
+    #[cfg(feature = "danger-accept-invalid-certs")]
+    #[test]
+    fn builder_can_disable_certificate_verification() {
+        let client = NotificationHubClient::builder(TEST_CONNECTION_STRING, "hub")
+            .danger_accept_invalid_certs(true)
+            .connect_timeout(std::time::Duration::from_millis(1))
+            .build();
+        assert!(client.is_ok());
+    }
+
+    #[cfg(feature = "blocking")]
+    #[test]
+    fn blocking_client_runs_requests_without_a_runtime() {
+        // The server needs a runtime of its own, which must not be entered
+        // while the blocking client runs.
+        let server_runtime = tokio::runtime::Runtime::new().unwrap();
+        let (addr, requests) = {
+            let _guard = server_runtime.enter();
+            recording_server(|_| {
+                response(
+                    200,
+                    r#"{"installationId":"installation","platform":"apns","pushChannel":"A"}"#,
+                )
+            })
+        };
+        let client = crate::blocking::NotificationHubClient::new(
+            test_client().with_connector(LocalConnector(addr)),
+        );
+
+        let installation = client.get_installation("installation").unwrap();
+        assert_eq!(installation.installation_id, "installation");
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].uri.path(), "/hub/installations/installation");
+    }
+
+    #[test]
+    fn redact_signature_masks_sig_parameter() {
+        assert_eq!(
+            crate::notification_hub_client::redact_signature(
+                "https://example.net/hub/messages?sr=x&sig=secret&se=1"
+            ),
+            "https://example.net/hub/messages?sr=x&sig=REDACTED&se=1"
+        );
+    }
+
+    #[cfg(feature = "debug-capture")]
+    #[test]
+    fn redact_authorization_keeps_only_the_scheme() {
+        use crate::debug_capture::redact_authorization;
+
+        assert_eq!(
+            redact_authorization("SharedAccessSignature sr=x&sig=secret&se=1&skn=name"),
+            "SharedAccessSignature sr=x&sig=REDACTED&se=1&skn=name"
+        );
+        assert_eq!(redact_authorization("Bearer secret"), "Bearer REDACTED");
+        assert_eq!(redact_authorization("Basic c2VjcmV0"), "Basic REDACTED");
+        assert_eq!(redact_authorization("secret"), "REDACTED");
+    }
+
+    #[test]
+    fn entity_path_must_agree_with_explicit_hub_name() {
+        const WITH_ENTITY_PATH: &str =
+            "Endpoint=sb://example.servicebus.windows.net/;SharedAccessKeyName=name;SharedAccessKey=key;EntityPath=my-hub";
+
+        assert!(
+            NotificationHubClient::from_connection_string_with_entity_path(WITH_ENTITY_PATH, None)
+                .is_ok()
+        );
+        assert!(
+            NotificationHubClient::from_connection_string_with_entity_path(
+                WITH_ENTITY_PATH,
+                Some("my-hub")
+            )
+            .is_ok()
+        );
+        assert!(matches!(
+            NotificationHubClient::from_connection_string_with_entity_path(
+                WITH_ENTITY_PATH,
+                Some("other-hub")
+            ),
+            Err(FromConnectionStringError::ConflictingEntityPath)
+        ));
+    }
+
+    #[tokio::test]
+    async fn cancelled_send_returns_cancelled() {
+        let cancellation_token = tokio_util::sync::CancellationToken::new();
+        cancellation_token.cancel();
+
+        let result = test_client()
+            .send_notification_with_cancellation(
+                NotificationRequest::default(),
+                None,
+                None,
+                &cancellation_token,
+            )
+            .await;
+        assert!(matches!(result, Err(NotificationRequestError::Cancelled)));
+    }
+
+    #[tokio::test]
+    async fn cancellation_interrupts_retry_backoff_on_any_request() {
+        let (client, requests) = recording_client(|_| response(503, ""));
+        let cancellation_token = tokio_util::sync::CancellationToken::new();
+        let client = client
+            .with_retry_policy(RetryPolicy::new(3, Duration::from_secs(30)))
+            .with_cancellation_token(cancellation_token.clone());
+
+        let cancel = cancellation_token.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            cancel.cancel();
+        });
+        let started = std::time::Instant::now();
+        let result = client.get_installation("installation").await;
+        assert!(matches!(result, Err(NotificationRequestError::Cancelled)));
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert_eq!(requests.lock().unwrap().len(), 1);
+
+        let result = client
+            .send_direct_notification(
+                NotificationRequest::new(Platform::Apple, "application/json", "{}"),
+                &["token"],
+            )
+            .await;
+        assert!(matches!(result, Err(NotificationRequestError::Cancelled)));
+        assert_eq!(requests.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn oversized_payload_is_rejected_before_sending() {
+        let (client, requests) = recording_client(|_| response(201, ""));
+        let request =
+            NotificationRequest::new("apple", "application/json;charset=utf-8", vec![b'a'; 5000]);
+
+        let result = client.send_direct_notification(request, &["token"]).await;
+        assert!(matches!(
+            result,
+            Err(NotificationRequestError::PayloadTooLarge {
+                size: 5000,
+                limit: 4096
+            })
+        ));
+        assert!(requests.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn client_can_be_used_through_notification_hub_trait() {
+        let (client, requests) = recording_client(|_| response(201, ""));
+        let hub: &dyn NotificationHub = &client;
+
+        hub.send_tag_expression_notification(
+            NotificationRequest::new(Platform::Apple, "application/json", "{}"),
+            "sports && !muted",
+        )
+        .await
+        .unwrap();
+
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].method, hyper::Method::POST);
+        assert_eq!(requests[0].uri.path(), "/hub/messages");
+        assert_eq!(
+            requests[0].headers["servicebusnotification-tags"],
+            "sports && !muted"
+        );
+    }
+
+    #[tokio::test]
+    async fn fan_out_send_returns_a_result_per_tag_expression() {
+        let (client, requests) = recording_client(|request| {
+            let status = match request.headers["servicebusnotification-tags"].as_bytes() {
+                b"tenant-a" => 201,
+                _ => 400,
+            };
+            response(status, "")
+        });
+        let request = NotificationRequest::new(Platform::Apple, "application/json", "{}");
+
+        let results = client
+            .send_tag_expression_notifications(request, vec!["tenant-a", "tenant-b"], 2)
+            .await;
+        assert_eq!(results.len(), 2);
+        assert!(results[0].is_ok());
+        assert!(matches!(
+            results[1],
+            Err(NotificationRequestError::InvalidHttpResponse { status, .. }) if status == 400
+        ));
+
+        let mut tags: Vec<_> = requests
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|request| request.headers["servicebusnotification-tags"].clone())
+            .collect();
+        tags.sort();
+        assert_eq!(tags, ["tenant-a", "tenant-b"]);
+    }
+
+    #[tokio::test]
+    async fn malformed_uri_returns_invalid_request() {
+        let client = test_client().with_hub_name("my hub");
+
+        let result = client.get_installation("installation").await;
+        assert!(matches!(
+            result,
+            Err(NotificationRequestError::InvalidRequest(_))
+        ));
+    }
+
+    #[test]
+    fn builder_reports_connection_string_errors() {
+        let result = NotificationHubClient::builder("Endpoint=sb://example/", "hub")
+            .timeout(Duration::from_secs(5))
+            .retry_policy(RetryPolicy::default())
+            .api_version("2020-06")
+            .build();
+        assert!(matches!(
+            result,
+            Err(FromConnectionStringError::FailedToGetSharedAccessKey)
+        ));
+    }
+
+    #[tokio::test]
+    async fn invalid_user_agent_is_rejected() {
+        assert!(DEFAULT_USER_AGENT.starts_with("azure-notificationhubs-rust/"));
+
+        let client = NotificationHubClient::builder(TEST_CONNECTION_STRING, "hub")
+            .user_agent("line\nbreak")
+            .build()
+            .unwrap();
+
+        let result = client.get_installation("installation").await;
+        assert!(matches!(
+            result,
+            Err(NotificationRequestError::InvalidHeader(header)) if header == "user-agent"
+        ));
+    }
+
+    #[test]
+    fn platform_parses_send_and_installation_names() {
+        assert_eq!("apns".parse::<Platform>().unwrap(), Platform::Apple);
+        assert_eq!("Apple".parse::<Platform>().unwrap(), Platform::Apple);
+        assert_eq!("wns".parse::<Platform>().unwrap(), Platform::Windows);
+        assert_eq!(
+            "aple".parse::<Platform>().unwrap(),
+            Platform::Other("aple".to_string())
+        );
+        assert_eq!(Platform::Fcm.to_string(), "gcm");
+        assert_eq!(Platform::Apple.as_installation_str(), "apns");
+    }
+
+    #[test]
+    fn installation_tag_helpers_avoid_duplicates() {
+        let mut installation: Installation = serde_json::from_value(serde_json::json!({
+            "installationId": "installation-1",
+            "userId": "user-1",
+            "lastActiveOn": "",
+            "expirationTime": "",
+            "lastUpdate": "",
+            "platform": "apns",
+            "pushChannel": "ABCDEF",
+            "expiredPushChannel": false,
+            "tags": ["news"],
+            "templates": {}
+        }))
+        .unwrap();
+
+        assert!(!installation.add_tag("news"));
+        assert!(installation.add_tag("sports"));
+        assert!(installation.has_tag("sports"));
+        assert!(installation.remove_tag("news"));
+        assert!(!installation.remove_tag("news"));
+        assert_eq!(installation.tags, vec!["sports".to_string()]);
+
+        assert_eq!(
+            serde_json::to_value(vec![
+                InstallationPatch::add_tag("sports"),
+                InstallationPatch::remove_tag("news"),
+                InstallationPatch::remove_tag("team/a~b"),
+                InstallationPatch::replace_tags(&["sports", "weather"]),
+            ])
+            .unwrap(),
+            serde_json::json!([
+                { "op": "add", "path": "/tags", "value": "sports" },
+                { "op": "remove", "path": "/tags/news" },
+                { "op": "remove", "path": "/tags/team~1a~0b" },
+                { "op": "replace", "path": "/tags", "value": r#"["sports","weather"]"# }
+            ])
+        );
+    }
+
+    #[test]
+    fn direct_batch_body_lists_notification_and_devices() {
+        let request = NotificationRequest::new(Platform::Apple, "application/json", MESSAGE_BODY);
+        let body = direct_batch_body(&request, &["token-1", "token-2"]).unwrap();
+        let body = std::str::from_utf8(&body).unwrap();
+
+        assert!(body
+            .starts_with("--notification-hub-direct-batch\r\nContent-Type: application/json\r\n"));
+        assert!(body.contains(&format!("name=notification\r\n\r\n{}\r\n", MESSAGE_BODY)));
+        assert!(body.contains("name=devices\r\n\r\n[\"token-1\",\"token-2\"]\r\n"));
+        assert!(body.ends_with("--notification-hub-direct-batch--\r\n"));
+    }
+
+    #[test]
+    fn invalid_response_body_keeps_a_snippet_of_the_body() {
+        let body = br#"{"error":"not an installation"}"#;
+        let err = serde_json::from_slice::<Installation>(body).err().unwrap();
+
+        match invalid_response_body(err, body) {
+            NotificationRequestError::InvalidResponseBody { message, body } => {
+                assert!(message.contains("missing field"));
+                assert_eq!(body, r#"{"error":"not an installation"}"#);
+            }
+            other => panic!("unexpected error: {}", other),
+        }
+
+        let long_body = "é".repeat(1000);
+        match invalid_response_body("bad", long_body.as_bytes()) {
+            NotificationRequestError::InvalidResponseBody { body, .. } => {
+                assert_eq!(body.len(), 1024)
+            }
+            other => panic!("unexpected error: {}", other),
+        }
+    }
+
+    #[test]
+    fn builder_rejects_endpoint_from_another_cloud() {
+        assert_eq!(
+            AzureCloud::UsGovernment.endpoint("contoso"),
+            "sb://contoso.servicebus.usgovcloudapi.net/"
+        );
+        assert!(AzureCloud::China.matches_endpoint("sb://contoso.servicebus.chinacloudapi.cn/"));
+        assert!(!AzureCloud::Public.matches_endpoint("sb://servicebus.windows.net/"));
+
+        let result = NotificationHubClient::builder(TEST_CONNECTION_STRING, "hub")
+            .cloud(AzureCloud::UsGovernment)
+            .build();
+        assert!(matches!(
+            result,
+            Err(FromConnectionStringError::EndpointNotInCloud)
+        ));
+    }
+
+    #[tokio::test]
+    async fn rate_limiter_delays_requests_beyond_the_burst() {
+        let limiter = RateLimiter::new(20);
+        let start = std::time::Instant::now();
+
+        for _ in 0..22 {
+            limiter.acquire().await;
+        }
+        assert!(start.elapsed() >= Duration::from_millis(80));
+    }
+
+    #[tokio::test]
+    async fn rate_limiter_pauses_once_the_reported_quota_is_used_up() {
+        let throttled = hyper::Response::builder()
+            .status(429)
+            .header("retry-after", "0")
+            .body(hyper::Body::empty())
+            .unwrap();
+        let hint = notification_response(&throttled).rate_limit.unwrap();
+        assert_eq!(hint.remaining, Some(0));
+        assert_eq!(hint.reset_after, Some(Duration::ZERO));
+
+        let res = hyper::Response::builder()
+            .status(201)
+            .header("x-ms-ratelimit-remaining-subscription-writes", "5")
+            .header("x-ms-ratelimit-remaining", "3")
+            .header("x-ms-ratelimit-reset", "10")
+            .body(hyper::Body::empty())
+            .unwrap();
+        assert_eq!(
+            notification_response(&res).rate_limit,
+            Some(RateLimitHint {
+                remaining: Some(3),
+                reset_after: Some(Duration::from_secs(10)),
+            })
+        );
+
+        let limiter = RateLimiter::new(100);
+        limiter.observe(&RateLimitHint {
+            remaining: Some(0),
+            reset_after: Some(Duration::from_millis(100)),
+        });
+        let start = std::time::Instant::now();
+        limiter.acquire().await;
+        assert!(start.elapsed() >= Duration::from_millis(90));
+    }
+
+    #[test]
+    fn tag_expressions_are_validated_locally() {
+        assert!(validate_tag_expression("sports").is_ok());
+        assert!(validate_tag_expression(
+            "(follows_RedSox || follows_Cardinals) && location:Boston"
+        )
+        .is_ok());
+        assert!(validate_tag_expression("!muted && $InstallationId:{abc-123}").is_ok());
+
+        assert!(validate_tag_expression("").is_err());
+        assert!(validate_tag_expression("(a || b").is_err());
+        assert!(validate_tag_expression("a || b)").is_err());
+        assert!(validate_tag_expression("a &&").is_err());
+        assert!(validate_tag_expression("a & b").is_err());
+        assert!(validate_tag_expression("a b").is_err());
+
+        let or_tags: Vec<String> = (0..20).map(|i| format!("t{}", i)).collect();
+        assert!(validate_tag_expression(&or_tags.join(" || ")).is_ok());
+        let and_tags: Vec<String> = (0..7).map(|i| format!("t{}", i)).collect();
+        assert!(validate_tag_expression(&and_tags.join(" && ")).is_err());
+    }
+
+    #[tokio::test]
+    async fn tagged_sends_reject_tags_that_would_change_the_expression() {
+        assert!(validate_tag("app-version:1.2.3").is_ok());
+        assert!(validate_tag("a||b").is_err());
+        assert!(validate_tag("").is_err());
+        assert!(validate_tag(&"a".repeat(121)).is_err());
+
+        let client = client_for_status(201);
+        let result = client
+            .send_tagged_notification(
+                NotificationRequest::new(Platform::Apple, "application/json", "{}"),
+                vec!["sports", "a||b"],
+            )
+            .await;
+        assert!(matches!(
+            result,
+            Err(NotificationRequestError::InvalidTagExpression(message)) if message.contains("'|'")
+        ));
+    }
+
+    #[test]
+    fn azure_errors_are_parsed_from_json_or_xml() {
+        assert_eq!(
+            AzureError::parse(
+                r#"{"error":{"code":"InvalidInstallation","message":"Bad push channel"}}"#
+            ),
+            Some(AzureError {
+                code: "InvalidInstallation".to_string(),
+                message: "Bad push channel".to_string(),
+            })
+        );
+        assert_eq!(
+            AzureError::parse(
+                "<Error><Code>400</Code><Detail>The tag expression is invalid.</Detail></Error>"
+            ),
+            Some(AzureError {
+                code: "400".to_string(),
+                message: "The tag expression is invalid.".to_string(),
+            })
+        );
+        assert_eq!(AzureError::parse("Service Unavailable"), None);
+    }
+
+    #[derive(Clone)]
+    struct RefusingConnector(std::sync::Arc<std::sync::atomic::AtomicUsize>);
+
+    impl hyper::service::Service<hyper::Uri> for RefusingConnector {
+        type Response = tokio::net::TcpStream;
+        type Error = std::io::Error;
+        type Future = futures::future::Ready<Result<Self::Response, Self::Error>>;
+
+        fn poll_ready(
+            &mut self,
+            _cx: &mut std::task::Context<'_>,
+        ) -> std::task::Poll<Result<(), Self::Error>> {
+            std::task::Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, _uri: hyper::Uri) -> Self::Future {
+            self.0.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            futures::future::ready(Err(std::io::ErrorKind::ConnectionRefused.into()))
+        }
+    }
+
+    #[tokio::test]
+    async fn custom_connector_is_used_for_requests() {
+        // `LocalConnector` carries requests for the Azure host to a local server.
+        let (client, requests) = recording_client(|_| response(200, ""));
+
+        assert!(client.installation_exists("installation").await.unwrap());
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(
+            requests[0].headers["host"],
+            "example.servicebus.windows.net"
+        );
+    }
+
+    /// A connector whose connections never complete.
+    #[derive(Clone)]
+    struct HangingConnector;
+
+    impl hyper::service::Service<hyper::Uri> for HangingConnector {
+        type Response = tokio::net::TcpStream;
+        type Error = std::io::Error;
+        type Future = futures::future::Pending<Result<Self::Response, Self::Error>>;
+
+        fn poll_ready(
+            &mut self,
+            _cx: &mut std::task::Context<'_>,
+        ) -> std::task::Poll<Result<(), Self::Error>> {
+            std::task::Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, _uri: hyper::Uri) -> Self::Future {
+            futures::future::pending()
+        }
+    }
+
+    #[tokio::test]
+    async fn custom_connector_survives_later_connection_settings() {
+        let (client, requests) = recording_client(|_| response(200, ""));
+        let client = client
+            .with_connect_timeout(Duration::from_secs(5))
+            .with_http2(false);
+        assert!(client.installation_exists("installation").await.unwrap());
+        assert_eq!(requests.lock().unwrap().len(), 1);
+
+        let client = test_client()
+            .with_connect_timeout(Duration::from_millis(50))
+            .with_connector(HangingConnector);
+        let err = client.get_installation("installation").await.err().unwrap();
+        assert!(err.is_timeout());
+    }
+
+    #[tokio::test]
+    async fn request_timeout_fires_when_azure_never_answers() {
+        let make_service = hyper::service::make_service_fn(|_| async {
+            Ok::<_, std::convert::Infallible>(hyper::service::service_fn(
+                |_: hyper::Request<hyper::Body>| {
+                    futures::future::pending::<Result<hyper::Response<hyper::Body>, hyper::Error>>()
+                },
+            ))
+        });
+        let server = hyper::Server::bind(&([127, 0, 0, 1], 0).into()).serve(make_service);
+        let addr = server.local_addr();
+        tokio::spawn(server);
+
+        let client = test_client()
+            .with_connector(LocalConnector(addr))
+            .with_request_timeout(Duration::from_millis(100));
+        let started = std::time::Instant::now();
+        let result = tokio::time::timeout(
+            Duration::from_secs(5),
+            client.get_installation("installation"),
+        )
+        .await
+        .expect("the request timeout did not fire");
+
+        assert!(matches!(result, Err(NotificationRequestError::Timeout)));
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
+
+    #[tokio::test]
+    async fn connect_failures_are_classified() {
+        let calls = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let client = test_client().with_connector(RefusingConnector(calls.clone()));
+
+        let err = client.get_installation("installation").await.err().unwrap();
+        assert!(matches!(err, NotificationRequestError::HttpRequestError(_)));
+        assert_eq!(calls.load(std::sync::atomic::Ordering::SeqCst), 1);
+        assert!(err.is_connect());
+        assert!(!err.is_protocol());
+        assert!(!err.is_timeout());
+    }
+
+    #[tokio::test]
+    async fn malformed_responses_are_protocol_errors() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            use tokio::io::{AsyncReadExt, AsyncWriteExt};
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let mut buf = [0; 4096];
+                let _ = stream.read(&mut buf).await;
+                let _ = stream.write_all(b"not http\r\n\r\n").await;
+            }
+        });
+        let client = test_client().with_connector(LocalConnector(addr));
+
+        let err = client.get_installation("installation").await.err().unwrap();
+        assert!(err.is_protocol());
+        assert!(!err.is_connect());
+
+        let misuse = hyper::Client::new()
+            .get(hyper::Uri::from_static("/relative"))
+            .await
+            .err()
+            .unwrap();
+        assert!(misuse.is_user());
+        assert!(!NotificationRequestError::from(misuse).is_protocol());
+    }
+
+    #[tokio::test]
+    async fn installation_ids_are_percent_encoded() {
+        let (client, requests) = recording_client(|_| response(204, ""));
+
+        client.delete_installation("a/b c").await.unwrap();
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests[0].uri.path(), "/hub/installations/a%2Fb%20c");
+    }
+
+    fn toast_stream(
+    ) -> impl futures::Stream<Item = Result<hyper::body::Bytes, std::io::Error>> + Send + 'static
+    {
+        futures::stream::iter(vec![
+            Ok(hyper::body::Bytes::from_static(b"<toast>")),
+            Ok(hyper::body::Bytes::from_static(b"</toast>")),
+        ])
+    }
+
+    #[tokio::test]
+    async fn streamed_sends_are_chunked_and_sent_once() {
+        let (client, requests) = recording_client(|_| {
+            hyper::Response::builder()
+                .status(201)
+                .header("trackingid", "tracking-1")
+                .header(
+                    "location",
+                    "https://example.servicebus.windows.net/hub/messages/abc-123?api-version=2020-06",
+                )
+                .body(hyper::Body::empty())
+                .unwrap()
+        });
+
+        let sent = client
+            .send_notification_stream(
+                NotificationRequest::new(Platform::Windows, "application/xml", ""),
+                toast_stream(),
+                Some("channel"),
+                None,
+            )
+            .await
+            .unwrap();
+        assert_eq!(sent.tracking_id.as_deref(), Some("tracking-1"));
+        assert_eq!(sent.notification_id.as_deref(), Some("abc-123"));
+        {
+            let requests = requests.lock().unwrap();
+            assert_eq!(requests.len(), 1);
+            assert_eq!(requests[0].headers["transfer-encoding"], "chunked");
+            assert_eq!(
+                requests[0].headers["servicebusnotification-devicehandle"],
+                "channel"
+            );
+            assert_eq!(&requests[0].body[..], b"<toast></toast>");
+        }
+
+        // A throttled streamed send is not retried, since its body is gone.
+        let (client, requests) = recording_client(|_| response(429, ""));
+        let client = client.with_retry_policy(RetryPolicy::new(3, Duration::from_millis(1)));
+        let result = client
+            .send_notification_stream(
+                NotificationRequest::new(Platform::Windows, "application/xml", ""),
+                toast_stream(),
+                Some("channel"),
+                None,
+            )
+            .await;
+        assert!(matches!(
+            result,
+            Err(NotificationRequestError::InvalidHttpResponse { status, .. }) if status == 429
+        ));
+        assert_eq!(requests.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn json_notifications_are_serialized_and_size_checked() {
+        let (client, requests) = recording_client(|_| response(201, ""));
+
+        let payload = serde_json::json!({ "aps": { "alert": "Hello" } });
+        client
+            .send_json_notification(&payload, Platform::Apple, vec!["sports"])
+            .await
+            .unwrap();
+        {
+            let requests = requests.lock().unwrap();
+            assert_eq!(requests.len(), 1);
+            assert_eq!(&requests[0].body[..], br#"{"aps":{"alert":"Hello"}}"#);
+            assert_eq!(
+                requests[0].headers["content-type"],
+                "application/json;charset=utf-8"
+            );
+            assert_eq!(requests[0].headers["servicebusnotification-tags"], "sports");
+        }
+
+        let payload = serde_json::json!({ "aps": { "alert": "x".repeat(5000) } });
+        let result = client
+            .send_json_notification(&payload, Platform::Apple, vec![])
+            .await;
+        assert!(matches!(
+            result,
+            Err(NotificationRequestError::PayloadTooLarge { .. })
+        ));
+        assert_eq!(requests.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn client_can_be_built_from_a_token_provider() {
+        let (addr, requests) = recording_server(|_| response(200, ""));
+        let provider = SasTokenProvider::new("name".to_string(), "key".to_string());
+        let client = NotificationHubClient::from_token_provider(
+            provider,
+            "sb://example.servicebus.windows.net/",
+            "hub",
+        )
+        .with_connector(LocalConnector(addr));
+
+        assert!(client.installation_exists("installation").await.unwrap());
+        let requests = requests.lock().unwrap();
+        let authorization = requests[0].headers["authorization"].to_str().unwrap();
+        assert!(authorization.starts_with("SharedAccessSignature sr="));
+        assert!(authorization.ends_with("&skn=name"));
+    }
+
+    /// Connects every request to a local server instead of the URI's host.
+    #[derive(Clone)]
+    struct LocalConnector(std::net::SocketAddr);
+
+    impl hyper::service::Service<hyper::Uri> for LocalConnector {
+        type Response = tokio::net::TcpStream;
+        type Error = std::io::Error;
+        type Future = futures::future::BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+        fn poll_ready(
+            &mut self,
+            _cx: &mut std::task::Context<'_>,
+        ) -> std::task::Poll<Result<(), Self::Error>> {
+            std::task::Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, _uri: hyper::Uri) -> Self::Future {
+            Box::pin(tokio::net::TcpStream::connect(self.0))
+        }
+    }
+
+    /// Starts a local HTTP server answering every request with `status`, and
+    /// returns a client whose requests are sent to it.
+    fn client_for_status(status: u16) -> NotificationHubClient {
+        client_for_response(status, "")
+    }
+
+    /// Like `client_for_status`, with every response carrying `body`.
+    fn client_for_response(status: u16, body: &'static str) -> NotificationHubClient {
+        recording_client(move |_| response(status, body)).0
+    }
+
+    fn response(status: u16, body: &'static str) -> hyper::Response<hyper::Body> {
+        hyper::Response::builder()
+            .status(status)
+            .body(hyper::Body::from(body))
+            .unwrap()
+    }
+
+    /// A request as received by a `recording_server`, body included.
+    #[derive(Clone, Debug)]
+    struct RecordedRequest {
+        method: hyper::Method,
+        uri: hyper::Uri,
+        headers: hyper::HeaderMap,
+        body: hyper::body::Bytes,
+    }
+
+    type RecordedRequests = std::sync::Arc<std::sync::Mutex<Vec<RecordedRequest>>>;
+
+    /// Starts a local HTTP server that answers each request with `respond`
+    /// and records it, returning the server's address and the recording.
+    fn recording_server<F>(respond: F) -> (std::net::SocketAddr, RecordedRequests)
+    where
+        F: Fn(&RecordedRequest) -> hyper::Response<hyper::Body> + Send + Sync + 'static,
+    {
+        let respond = std::sync::Arc::new(respond);
+        let requests = RecordedRequests::default();
+        let recorded = requests.clone();
+        let make_service = hyper::service::make_service_fn(move |_| {
+            let respond = respond.clone();
+            let recorded = recorded.clone();
+            async move {
+                Ok::<_, std::convert::Infallible>(hyper::service::service_fn(
+                    move |req: hyper::Request<hyper::Body>| {
+                        let respond = respond.clone();
+                        let recorded = recorded.clone();
+                        async move {
+                            let (parts, body) = req.into_parts();
+                            let request = RecordedRequest {
+                                method: parts.method,
+                                uri: parts.uri,
+                                headers: parts.headers,
+                                body: hyper::body::to_bytes(body).await?,
+                            };
+                            let res = respond(&request);
+                            recorded.lock().unwrap().push(request);
+                            Ok::<_, hyper::Error>(res)
+                        }
+                    },
+                ))
+            }
+        });
+        let server = hyper::Server::bind(&([127, 0, 0, 1], 0).into()).serve(make_service);
+        let addr = server.local_addr();
+        tokio::spawn(server);
+        (addr, requests)
+    }
+
+    /// Like `recording_server`, returning a `test_client` whose requests are
+    /// sent to it.
+    fn recording_client<F>(respond: F) -> (NotificationHubClient, RecordedRequests)
+    where
+        F: Fn(&RecordedRequest) -> hyper::Response<hyper::Body> + Send + Sync + 'static,
+    {
+        let (addr, requests) = recording_server(respond);
+        (test_client().with_connector(LocalConnector(addr)), requests)
+    }
+
+    #[tokio::test]
+    async fn installation_exists_maps_not_found_to_false() {
+        let client = client_for_status(200);
+        assert!(client.installation_exists("installation").await.unwrap());
+
+        let client = client_for_status(404);
+        assert!(!client.installation_exists("installation").await.unwrap());
+
+        let client = client_for_status(401);
+        assert!(matches!(
+            client.installation_exists("installation").await,
+            Err(NotificationRequestError::Unauthorized { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn scheduled_sends_carry_the_schedule_time() {
+        use chrono::TimeZone;
+
+        let (client, requests) = recording_client(|_| {
+            let mut res = response(201, "");
+            res.headers_mut().insert(
+                hyper::header::LOCATION,
+                hyper::header::HeaderValue::from_static(
+                    "https://example.servicebus.windows.net/hub/schedulednotifications/abc-123?api-version=2017-04",
+                ),
+            );
+            res
+        });
+
+        let scheduled = client
+            .send_scheduled_notification(
+                NotificationRequest::new(Platform::Apple, "application/json", MESSAGE_BODY),
+                chrono::Utc.with_ymd_and_hms(2030, 1, 2, 3, 4, 5).unwrap(),
+                vec!["sports", "news"],
+            )
+            .await
+            .unwrap();
+        assert_eq!(scheduled.notification_id, "abc-123");
+        assert!(scheduled.location.ends_with("/abc-123?api-version=2017-04"));
+
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 1);
+        let request = &requests[0];
+        assert_eq!(request.method, hyper::Method::POST);
+        assert_eq!(request.uri.path(), "/hub/schedulednotifications");
+        assert_eq!(
+            request.uri.query(),
+            Some(format!("api-version={}", DEFAULT_API_VERSION).as_str())
+        );
+        assert_eq!(
+            request.headers["servicebusnotification-scheduletime"],
+            "2030-01-02T03:04:05Z"
+        );
+        assert_eq!(
+            request.headers["servicebusnotification-tags"],
+            "sports||news"
+        );
+        assert_eq!(&request.body[..], MESSAGE_BODY.as_bytes());
+    }
+
+    #[tokio::test]
+    async fn cancelling_a_scheduled_send_can_override_the_api_version() {
+        let (client, requests) = recording_client(|_| response(200, ""));
+
+        client
+            .cancel_scheduled_notification("notification")
+            .await
+            .unwrap();
+        client
+            .cancel_scheduled_notification_with_api_version("notification", "2016-07")
+            .await
+            .unwrap();
+
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests[0].method, hyper::Method::DELETE);
+        assert_eq!(
+            requests[0].uri.path(),
+            "/hub/schedulednotifications/notification"
+        );
+        assert_eq!(
+            requests[0].uri.query(),
+            Some(format!("api-version={}", DEFAULT_API_VERSION).as_str())
+        );
+        assert_eq!(requests[1].uri.query(), Some("api-version=2016-07"));
+    }
+
+    #[cfg(feature = "compression")]
+    #[tokio::test]
+    async fn gzip_requests_and_responses_are_transparent() {
+        use flate2::read::GzDecoder;
+        use flate2::write::GzEncoder;
+        use std::io::{Read, Write};
+
+        const INSTALLATION: &str =
+            r#"{"installationId":"installation-1","platform":"apns","pushChannel":"ABCDEF"}"#;
+
+        // Checks that any request body arrives gzipped, and answers every
+        // request with a gzipped installation.
+        let make_service = hyper::service::make_service_fn(|_| async {
+            Ok::<_, std::convert::Infallible>(hyper::service::service_fn(
+                |req: hyper::Request<hyper::Body>| async move {
+                    assert_eq!(req.headers()["accept-encoding"], "gzip, deflate");
+                    if req.method() == hyper::Method::PUT {
+                        assert_eq!(req.headers()["content-encoding"], "gzip");
+                        let body = hyper::body::to_bytes(req.into_body()).await.unwrap();
+                        let mut json = String::new();
+                        GzDecoder::new(&body[..]).read_to_string(&mut json).unwrap();
+                        assert!(json.contains("installation-1"));
+                    }
+
+                    let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+                    encoder.write_all(INSTALLATION.as_bytes()).unwrap();
+                    hyper::Response::builder()
+                        .header("content-encoding", "gzip")
+                        .body(hyper::Body::from(encoder.finish().unwrap()))
+                },
+            ))
+        });
+        let server = hyper::Server::bind(&([127, 0, 0, 1], 0).into()).serve(make_service);
+        let addr = server.local_addr();
+        tokio::spawn(server);
+
+        let client = test_client()
+            .with_connector(LocalConnector(addr))
+            .with_gzip_requests(true);
+
+        let installation = client.get_installation("installation-1").await.unwrap();
+        assert_eq!(installation.installation_id, "installation-1");
+        assert!(client.upsert_installation(installation).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn request_metrics_are_reported_after_each_request() {
+        let reported = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let sink = reported.clone();
+        let client = client_for_status(201)
+            .with_on_request_complete(move |metrics| sink.lock().unwrap().push(metrics));
+
+        client
+            .send_direct_notification(
+                NotificationRequest::new(Platform::Apple, "application/json", MESSAGE_BODY),
+                &["token"],
+            )
+            .await
+            .unwrap();
+
+        let reported = reported.lock().unwrap();
+        assert_eq!(reported.len(), 1);
+        assert_eq!(reported[0].method, hyper::Method::POST);
+        assert_eq!(reported[0].platform, Some(Platform::Apple));
+        assert_eq!(reported[0].status, Some(hyper::StatusCode::CREATED));
+        assert_eq!(reported[0].bytes_sent, MESSAGE_BODY.len() as u64);
+        assert_eq!(reported[0].bytes_received, Some(0));
+    }
+
+    #[tokio::test]
+    async fn request_metrics_are_reported_for_streamed_sends() {
+        let reported = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let sink = reported.clone();
+        let client = client_for_status(201)
+            .with_on_request_complete(move |metrics| sink.lock().unwrap().push(metrics));
+
+        client
+            .send_notification_stream(
+                NotificationRequest::new(Platform::Windows, "application/xml", ""),
+                toast_stream(),
+                Some("channel"),
+                None,
+            )
+            .await
+            .unwrap();
+
+        let reported = reported.lock().unwrap();
+        assert_eq!(reported.len(), 1);
+        assert_eq!(reported[0].method, hyper::Method::POST);
+        assert_eq!(reported[0].platform, Some(Platform::Windows));
+        assert_eq!(reported[0].status, Some(hyper::StatusCode::CREATED));
+        assert_eq!(reported[0].bytes_sent, 0);
+    }
+
+    /// Like `recording_client`, serving the installations in two pages joined
+    /// by the continuation token `page 2`.
+    fn paged_installations_client() -> (NotificationHubClient, RecordedRequests) {
+        recording_client(|request| {
+            if request.uri.query().unwrap().contains("continuationtoken=") {
+                return response(
+                    200,
+                    r#"[{"installationId":"third","platform":"apns","pushChannel":"C","tags":["app-v1"]}]"#,
+                );
+            }
+            let mut res = response(
+                200,
+                r#"[
+                    {"installationId":"first","platform":"apns","pushChannel":"A","tags":["app-v1"]},
+                    {"installationId":"second","platform":"apns","pushChannel":"B","tags":["app-v2"]}
+                ]"#,
+            );
+            res.headers_mut().insert(
+                "x-ms-continuationtoken",
+                hyper::header::HeaderValue::from_static("page 2"),
+            );
+            res
+        })
+    }
+
+    #[tokio::test]
+    async fn installations_are_listed_across_pages() {
+        use futures::TryStreamExt;
+
+        let (client, requests) = paged_installations_client();
+        let installations: Vec<Installation> =
+            client.list_installations().try_collect().await.unwrap();
+        let ids: Vec<&str> = installations
+            .iter()
+            .map(|installation| installation.installation_id.as_str())
+            .collect();
+        assert_eq!(ids, ["first", "second", "third"]);
+
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[0].uri.path(), "/hub/installations");
+        assert!(!requests[0]
+            .uri
+            .query()
+            .unwrap()
+            .contains("continuationtoken"));
+        assert!(requests[1]
+            .uri
+            .query()
+            .unwrap()
+            .contains("continuationtoken=page%202"));
+    }
+
+    #[tokio::test]
+    async fn installations_are_filtered_while_streaming() {
+        use futures::TryStreamExt;
+
+        let (client, requests) = paged_installations_client();
+        let installations: Vec<Installation> = client
+            .list_installations_filtered(|installation| {
+                installation.tags.iter().any(|tag| tag == "app-v1")
+            })
+            .try_collect()
+            .await
+            .unwrap();
+        let ids: Vec<&str> = installations
+            .iter()
+            .map(|installation| installation.installation_id.as_str())
+            .collect();
+        assert_eq!(ids, ["first", "third"]);
+        assert_eq!(requests.lock().unwrap().len(), 2);
+    }
+
+    #[cfg(feature = "debug-capture")]
+    #[tokio::test]
+    async fn debug_capture_sees_the_request_as_sent() {
+        let captured = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let sink = captured.clone();
+        let client = client_for_response(400, "bad payload")
+            .with_debug_capture(move |exchange| sink.lock().unwrap().push(exchange.clone()));
+
+        let result = client
+            .send_direct_notification(
+                NotificationRequest::new(Platform::Apple, "application/json", MESSAGE_BODY),
+                &["token"],
+            )
+            .await;
+        assert!(result.is_err());
+
+        let captured = captured.lock().unwrap();
+        assert_eq!(captured.len(), 1);
+        let request = &captured[0].request;
+        assert_eq!(request.method, hyper::Method::POST);
+        assert_eq!(&request.body[..], MESSAGE_BODY.as_bytes());
+        let authorization = request.headers["authorization"].to_str().unwrap();
+        assert!(authorization.contains("&sig=REDACTED&"));
+        let response = captured[0].response.as_ref().unwrap();
+        assert_eq!(response.status, hyper::StatusCode::BAD_REQUEST);
+        assert_eq!(&response.body[..], b"bad payload");
+    }
+
+    #[cfg(feature = "debug-capture")]
+    #[tokio::test]
+    async fn debug_capture_masks_bearer_tokens() {
+        const TOKEN: &str = "eyJ0eXAiOiJKV1QifQ.secret-access-token";
+        let captured = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let sink = captured.clone();
+        let (addr, _) = recording_server(|_| response(201, ""));
+        let client = NotificationHubClient::from_bearer_token(
+            || async { Ok(TOKEN.to_string()) },
+            "sb://example.servicebus.windows.net/",
+            "hub",
+        )
+        .with_connector(LocalConnector(addr))
+        .with_debug_capture(move |exchange| sink.lock().unwrap().push(exchange.clone()));
+
+        client
+            .send_direct_notification(
+                NotificationRequest::new(Platform::Apple, "application/json", MESSAGE_BODY),
+                &["token"],
+            )
+            .await
+            .unwrap();
+
+        let captured = captured.lock().unwrap();
+        assert_eq!(captured.len(), 1);
+        assert_eq!(
+            captured[0].request.headers["authorization"],
+            "Bearer REDACTED"
+        );
+        assert!(!format!("{:?}", captured[0]).contains(TOKEN));
+    }
+
+    #[tokio::test]
+    async fn same_host_redirects_are_followed_when_enabled() {
+        let make_service = hyper::service::make_service_fn(|_| async {
+            Ok::<_, std::convert::Infallible>(hyper::service::service_fn(
+                |req: hyper::Request<hyper::Body>| async move {
+                    if req.uri().path().ends_with("/installations/old") {
+                        hyper::Response::builder()
+                            .status(307)
+                            .header("location", "/hub/installations/new?api-version=2020-06")
+                            .body(hyper::Body::empty())
+                    } else {
+                        hyper::Response::builder().body(hyper::Body::from(
+                            r#"{"installationId":"new","platform":"apns","pushChannel":"A"}"#,
+                        ))
+                    }
+                },
+            ))
+        });
+        let server = hyper::Server::bind(&([127, 0, 0, 1], 0).into()).serve(make_service);
+        let addr = server.local_addr();
+        tokio::spawn(server);
+
+        let client = test_client().with_connector(LocalConnector(addr));
+
+        match client.get_installation("old").await {
+            Err(NotificationRequestError::Redirect { status, location }) => {
+                assert_eq!(status, 307);
+                assert_eq!(
+                    location.as_deref(),
+                    Some("/hub/installations/new?api-version=2020-06")
+                );
+            }
+            other => panic!("expected a redirect error, got {:?}", other.err()),
+        }
+
+        let client = client.with_max_redirects(1);
+        let installation = client.get_installation("old").await.unwrap();
+        assert_eq!(installation.installation_id, "new");
+    }
+
+    struct FixedTokenProvider(Option<&'static str>);
+
+    #[async_trait::async_trait]
+    impl TokenProvider for FixedTokenProvider {
+        async fn token(&self, target_url: &str) -> Result<String, TokenProviderError> {
+            assert_eq!(target_url, "sb://example.servicebus.windows.net/");
+            self.0
+                .map(str::to_string)
+                .ok_or_else(|| "no token available".into())
+        }
+    }
+
+    /// Serves 200 to requests whose `Authorization` header is `expected` and
+    /// 401 to any other.
+    fn server_requiring_authorization(expected: &'static str) -> std::net::SocketAddr {
+        let make_service = hyper::service::make_service_fn(move |_| async move {
+            Ok::<_, std::convert::Infallible>(hyper::service::service_fn(
+                move |req: hyper::Request<hyper::Body>| async move {
+                    let status = if req.headers()["authorization"] == expected {
+                        200
+                    } else {
+                        401
+                    };
+                    hyper::Response::builder()
+                        .status(status)
+                        .body(hyper::Body::empty())
+                },
+            ))
+        });
+        let server = hyper::Server::bind(&([127, 0, 0, 1], 0).into()).serve(make_service);
+        let addr = server.local_addr();
+        tokio::spawn(server);
+        addr
+    }
+
+    #[tokio::test]
+    async fn custom_token_providers_authorize_requests() {
+        let addr = server_requiring_authorization("SharedAccessSignature custom");
+
+        let client = NotificationHubClient::from_custom_token_provider(
+            FixedTokenProvider(Some("SharedAccessSignature custom")),
+            "sb://example.servicebus.windows.net/",
+            "hub",
+        )
+        .with_connector(LocalConnector(addr));
+        assert!(client.installation_exists("installation").await.unwrap());
+
+        let client = NotificationHubClient::from_custom_token_provider(
+            FixedTokenProvider(None),
+            "sb://example.servicebus.windows.net/",
+            "hub",
+        )
+        .with_connector(LocalConnector(addr));
+        assert!(matches!(
+            client.installation_exists("installation").await,
+            Err(NotificationRequestError::TokenProviderError(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn bearer_tokens_are_sent_in_place_of_sas_tokens() {
+        let addr = server_requiring_authorization("Bearer access-token");
+
+        let client = NotificationHubClient::from_bearer_token(
+            || async { Ok("access-token".to_string()) },
+            "sb://example.servicebus.windows.net/",
+            "hub",
+        )
+        .with_connector(LocalConnector(addr));
+        assert!(client.installation_exists("installation").await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn registration_sends_go_directly_to_its_handle() {
+        let (client, requests) = recording_client(|request| {
+            if request.method == hyper::Method::GET {
+                return response(
+                    200,
+                    r#"<entry xmlns="http://www.w3.org/2005/Atom">
+                        <content type="application/xml">
+                            <AppleRegistrationDescription xmlns="http://schemas.microsoft.com/netservices/2010/10/servicebus/connect">
+                                <RegistrationId>8155</RegistrationId>
+                                <DeviceToken>ABCDEF</DeviceToken>
+                            </AppleRegistrationDescription>
+                        </content>
+                    </entry>"#,
+                );
+            }
+            response(201, "")
+        });
+
+        client
+            .send_to_registration(
+                "8155",
+                NotificationRequest::new(Platform::Apple, "application/json", "{}"),
+            )
+            .await
+            .unwrap();
+
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[0].uri.path(), "/hub/registrations/8155");
+
+        let send = &requests[1];
+        assert_eq!(send.method, hyper::Method::POST);
+        assert_eq!(send.uri.path(), "/hub/messages");
+        assert!(send.uri.query().unwrap().contains("direct=true"));
+        assert_eq!(
+            send.headers["servicebusnotification-devicehandle"],
+            "ABCDEF"
+        );
+        assert!(!send.headers.contains_key("servicebusnotification-tags"));
+    }
+
+    #[tokio::test]
+    async fn awaiting_an_outcome_needs_a_notification_id() {
+        let client = client_for_status(201);
+        let result = client
+            .send_and_await_outcome(
+                NotificationRequest::new(Platform::Apple, "application/json", "{}"),
+                vec!["sports"],
+                std::time::Duration::from_millis(10),
+                std::time::Duration::from_secs(1),
+            )
+            .await;
+        assert!(matches!(
+            result,
+            Err(NotificationRequestError::TelemetryUnavailable)
+        ));
+    }
+
+    #[tokio::test]
+    async fn awaiting_an_outcome_polls_until_the_state_is_final() {
+        let polls = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let counter = polls.clone();
+        let (client, requests) = recording_client(move |request| {
+            if request.method == hyper::Method::POST {
+                let mut res = response(201, "");
+                res.headers_mut().insert(
+                    hyper::header::LOCATION,
+                    hyper::header::HeaderValue::from_static(
+                        "https://example.servicebus.windows.net/hub/messages/abc-123?api-version=2017-04",
+                    ),
+                );
+                return res;
+            }
+            let state = match counter.fetch_add(1, std::sync::atomic::Ordering::SeqCst) {
+                0 => "Enqueued",
+                _ => "Completed",
+            };
+            let xml = format!(
+                r#"<NotificationDetails xmlns="http://schemas.microsoft.com/netservices/2010/10/servicebus/connect">
+                    <NotificationId>abc-123</NotificationId>
+                    <State>{}</State>
+                </NotificationDetails>"#,
+                state
+            );
+            hyper::Response::new(hyper::Body::from(xml))
+        });
+
+        let telemetry = client
+            .send_and_await_outcome(
+                NotificationRequest::new(Platform::Apple, "application/json", "{}"),
+                vec!["sports"],
+                std::time::Duration::from_millis(10),
+                std::time::Duration::from_secs(5),
+            )
+            .await
+            .unwrap();
+        assert_eq!(telemetry.notification_id, "abc-123");
+        assert_eq!(telemetry.state, "Completed");
+        assert_eq!(polls.load(std::sync::atomic::Ordering::SeqCst), 2);
+
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 3);
+        assert_eq!(requests[0].uri.path(), "/hub/messages");
+        for poll in &requests[1..] {
+            assert_eq!(poll.method, hyper::Method::GET);
+            assert_eq!(poll.uri.path(), "/hub/messages/abc-123");
+        }
+    }
+
+    #[tokio::test]
+    async fn direct_sends_pick_an_encoding_by_handle_count() {
+        let (client, requests) = recording_client(|_| response(201, ""));
+        let request = NotificationRequest::new(Platform::Apple, "application/json", "{}");
+
+        client
+            .send_direct_notification(request.clone(), &["one"])
+            .await
+            .unwrap();
+        client
+            .send_direct_notification(request.clone(), &["one", "two"])
+            .await
+            .unwrap();
+        assert!(matches!(
+            client.send_direct_notification(request, &[]).await,
+            Err(NotificationRequestError::NoDeviceHandles)
+        ));
+
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 2);
+
+        let single = &requests[0];
+        assert_eq!(single.uri.path(), "/hub/messages");
+        assert!(single.uri.query().unwrap().contains("direct=true"));
+        assert_eq!(single.headers["servicebusnotification-devicehandle"], "one");
+        assert_eq!(&single.body[..], b"{}");
+
+        let batch = &requests[1];
+        assert_eq!(batch.uri.path(), "/hub/messages/$batch");
+        assert!(batch.uri.query().unwrap().starts_with("direct&"));
+        assert!(!batch
+            .headers
+            .contains_key("servicebusnotification-devicehandle"));
+        assert!(batch.headers[hyper::header::CONTENT_TYPE]
+            .to_str()
+            .unwrap()
+            .starts_with("multipart/mixed; boundary="));
+        let body = std::str::from_utf8(&batch.body).unwrap();
+        assert!(body.contains("name=devices\r\n\r\n[\"one\",\"two\"]\r\n"));
+    }
+
+    #[test]
+    fn config_loads_from_partial_documents() {
+        let config: NotificationHubConfig = serde_json::from_value(serde_json::json!({
+            "timeout": 2500,
+            "retry_policy": { "max_attempts": 5 },
+            "user_agent": "my-app/1.0"
+        }))
+        .unwrap();
+
+        assert_eq!(config.timeout, Some(std::time::Duration::from_millis(2500)));
+        assert_eq!(config.connect_timeout, None);
+        let retry_policy = config.retry_policy.as_ref().unwrap();
+        assert_eq!(retry_policy.max_attempts, 5);
+        assert_eq!(retry_policy.base_delay, RetryPolicy::default().base_delay);
+        assert_eq!(
+            serde_json::to_value(&config).unwrap()["timeout"],
+            serde_json::json!(2500)
+        );
+
+        assert!(NotificationHubClient::with_config(TEST_CONNECTION_STRING, "hub", config,).is_ok());
+    }
+
+    #[tokio::test]
+    async fn conditional_create_reports_existing_installations() {
+        let installation = || -> Installation {
+            serde_json::from_value(serde_json::json!({
+                "installationId": "installation-1",
+                "platform": "apns",
+                "pushChannel": "ABCDEF"
+            }))
+            .unwrap()
+        };
+
+        let client = client_for_status(200);
+        assert!(client
+            .upsert_installation_if_absent(installation())
+            .await
+            .is_ok());
+
+        let client = client_for_status(412);
+        assert!(matches!(
+            client.upsert_installation_if_absent(installation()).await,
+            Err(NotificationRequestError::AlreadyExists)
+        ));
+    }
+
+    #[tokio::test]
+    async fn check_connection_reports_network_failures() {
+        let calls = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let client = test_client().with_connector(RefusingConnector(calls.clone()));
+
+        let result = client.check_connection().await;
+        assert!(matches!(
+            result,
+            Err(NotificationRequestError::HttpRequestError(_))
+        ));
+        assert_eq!(calls.load(std::sync::atomic::Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn check_connection_treats_not_found_as_authenticated() {
+        assert!(client_for_status(404).check_connection().await.is_ok());
+
+        for status in [401, 403] {
+            assert!(matches!(
+                client_for_status(status).check_connection().await,
+                Err(NotificationRequestError::Unauthorized { status: rejected, .. })
+                    if rejected == status
+            ));
+        }
+    }
+
+    #[test]
+    fn notification_id_is_read_from_location() {
+        assert_eq!(
+            notification_id_from_location(
+                "https://example.servicebus.windows.net/hub/messages/abc-123?api-version=2017-04"
+            ),
+            Some("abc-123".to_string())
+        );
+    }
+
+    #[test]
+    fn client_is_send_and_sync() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<NotificationHubClient>();
     }
 }
@@ -0,0 +1,171 @@
+use crate::notification_hub_client::{
+    error_from_response, invalid_response_body, read_expected_body, FromConnectionStringError,
+    NotificationHubClient, NotificationRequestError,
+};
+use crate::platform::Platform;
+use hyper::body::Bytes;
+use hyper::header::HeaderMap;
+use hyper::{Method, StatusCode};
+use serde::de::IgnoredAny;
+use serde::Deserialize;
+
+/// A notification hub as described by its namespace. Only whether each
+/// platform's credentials are configured is reported, never the credentials.
+#[derive(Clone, Debug, Default)]
+pub struct NotificationHubDescription {
+    pub name: String,
+    /// How long registrations live without being updated, as an ISO 8601
+    /// duration such as `P39D`
+    pub registration_ttl: Option<String>,
+    /// The platforms the hub has PNS credentials for
+    pub platforms: Vec<Platform>,
+}
+
+impl NotificationHubDescription {
+    pub(crate) fn from_atom_entry<R: std::io::BufRead>(
+        reader: R,
+    ) -> Result<Self, quick_xml::DeError> {
+        let entry: EntryIn = quick_xml::de::from_reader(reader)?;
+        Ok(entry.into())
+    }
+
+    pub(crate) fn from_atom_feed<R: std::io::BufRead>(
+        reader: R,
+    ) -> Result<Vec<Self>, quick_xml::DeError> {
+        let feed: FeedIn = quick_xml::de::from_reader(reader)?;
+        Ok(feed.entries.into_iter().map(Self::from).collect())
+    }
+}
+
+/// Manages the notification hubs of a namespace. Needs a connection string
+/// for a namespace-level shared access policy with the `Manage` claim, such as
+/// `RootManageSharedAccessKey`.
+#[derive(Clone)]
+pub struct NamespaceClient {
+    client: NotificationHubClient,
+}
+
+impl NamespaceClient {
+    pub fn from_connection_string(
+        connection_string: &str,
+    ) -> Result<NamespaceClient, FromConnectionStringError> {
+        Ok(Self::from_client(
+            NotificationHubClient::from_connection_string(connection_string, "")?,
+        ))
+    }
+
+    /// Wraps a client configured with `NotificationHubClient`'s builder, to
+    /// reuse its timeouts, retry policy and connector. Its hub name is ignored.
+    pub fn from_client(client: NotificationHubClient) -> Self {
+        Self { client }
+    }
+
+    /// A client for one of the namespace's hubs, sharing this client's
+    /// credentials and settings.
+    pub fn notification_hub_client(&self, hub_name: &str) -> NotificationHubClient {
+        self.client.clone().with_hub_name(hub_name)
+    }
+
+    pub async fn list_notification_hubs(
+        &self,
+    ) -> Result<Vec<NotificationHubDescription>, NotificationRequestError> {
+        let uri = self.client.namespace_uri("$Resources/NotificationHubs");
+
+        let res = self
+            .client
+            .execute(Method::GET, &uri, HeaderMap::new(), Bytes::new())
+            .await?;
+        if res.status() != StatusCode::OK {
+            return Err(error_from_response(res).await);
+        }
+
+        let body = read_expected_body(res).await?;
+        NotificationHubDescription::from_atom_feed(&body[..])
+            .map_err(|err| invalid_response_body(err, &body))
+    }
+
+    pub async fn get_notification_hub(
+        &self,
+        hub_name: &str,
+    ) -> Result<NotificationHubDescription, NotificationRequestError> {
+        let uri = self.client.namespace_uri(&urlencoding::encode(hub_name));
+
+        let res = self
+            .client
+            .execute(Method::GET, &uri, HeaderMap::new(), Bytes::new())
+            .await?;
+        match res.status() {
+            StatusCode::OK => {}
+            StatusCode::NOT_FOUND => return Err(NotificationRequestError::NotFound),
+            _ => return Err(error_from_response(res).await),
+        }
+
+        let body = read_expected_body(res).await?;
+        NotificationHubDescription::from_atom_entry(&body[..])
+            .map_err(|err| invalid_response_body(err, &body))
+    }
+}
+
+#[derive(Deserialize)]
+struct FeedIn {
+    #[serde(rename = "entry", default)]
+    entries: Vec<EntryIn>,
+}
+
+#[derive(Deserialize)]
+struct EntryIn {
+    title: TitleIn,
+    content: ContentIn,
+}
+
+#[derive(Deserialize)]
+struct TitleIn {
+    #[serde(rename = "$text", default)]
+    text: String,
+}
+
+#[derive(Deserialize)]
+struct ContentIn {
+    #[serde(rename = "NotificationHubDescription")]
+    description: DescriptionIn,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct DescriptionIn {
+    #[serde(default)]
+    registration_ttl: Option<String>,
+    #[serde(default)]
+    apns_credential: Option<IgnoredAny>,
+    #[serde(default)]
+    gcm_credential: Option<IgnoredAny>,
+    #[serde(default)]
+    fcm_v1_credential: Option<IgnoredAny>,
+    #[serde(default)]
+    wns_credential: Option<IgnoredAny>,
+    #[serde(default)]
+    baidu_credential: Option<IgnoredAny>,
+}
+
+impl From<EntryIn> for NotificationHubDescription {
+    fn from(entry: EntryIn) -> Self {
+        let description = entry.content.description;
+        let platforms = [
+            (Platform::Apple, description.apns_credential.is_some()),
+            (Platform::Fcm, description.gcm_credential.is_some()),
+            (Platform::FcmV1, description.fcm_v1_credential.is_some()),
+            (Platform::Windows, description.wns_credential.is_some()),
+            (Platform::Baidu, description.baidu_credential.is_some()),
+        ]
+        .into_iter()
+        .filter(|(_, configured)| *configured)
+        .map(|(platform, _)| platform)
+        .collect();
+
+        Self {
+            name: entry.title.text,
+            registration_ttl: description.registration_ttl,
+            platforms,
+        }
+    }
+}
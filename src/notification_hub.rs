@@ -0,0 +1,239 @@
+use crate::notification_hub_client::{
+    Installation, InstallationPage, InstallationPatch, InstallationPathResponse,
+    NotificationHubClient, NotificationRequest, NotificationRequestError, NotificationResponse,
+    ScheduledNotificationResponse,
+};
+use crate::notification_telemetry::NotificationTelemetry;
+use crate::registration::RegistrationDescription;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+
+/// The operations of a notification hub, implemented by `NotificationHubClient`.
+///
+/// Code that depends on this trait rather than the concrete client can be
+/// tested against a mock implementation instead of a live hub.
+#[async_trait]
+pub trait NotificationHub: Send + Sync {
+    async fn get_installation(
+        &self,
+        installation_id: &str,
+    ) -> Result<Installation, NotificationRequestError>;
+
+    async fn upsert_installation(
+        &self,
+        installation: Installation,
+    ) -> Result<InstallationPathResponse, NotificationRequestError>;
+
+    async fn patch_installation(
+        &self,
+        installation_id: &str,
+        patches: Vec<InstallationPatch>,
+    ) -> Result<InstallationPathResponse, NotificationRequestError>;
+
+    async fn list_installations_page(
+        &self,
+        continuation_token: Option<&str>,
+    ) -> Result<InstallationPage, NotificationRequestError>;
+
+    async fn delete_installation(
+        &self,
+        installation_id: &str,
+    ) -> Result<(), NotificationRequestError>;
+
+    async fn create_registration(
+        &self,
+        registration: RegistrationDescription,
+    ) -> Result<RegistrationDescription, NotificationRequestError>;
+
+    async fn get_registration(
+        &self,
+        registration_id: &str,
+    ) -> Result<RegistrationDescription, NotificationRequestError>;
+
+    async fn update_registration(
+        &self,
+        registration_id: &str,
+        registration: RegistrationDescription,
+    ) -> Result<RegistrationDescription, NotificationRequestError>;
+
+    async fn delete_registration(
+        &self,
+        registration_id: &str,
+    ) -> Result<(), NotificationRequestError>;
+
+    async fn send_direct_notification(
+        &self,
+        request_message: NotificationRequest,
+        device_tokens: &[&str],
+    ) -> Result<NotificationResponse, NotificationRequestError>;
+
+    async fn send_tagged_notification(
+        &self,
+        request_message: NotificationRequest,
+        tags: Vec<&str>,
+    ) -> Result<NotificationResponse, NotificationRequestError>;
+
+    async fn send_tag_expression_notification(
+        &self,
+        request_message: NotificationRequest,
+        tag_expression: &str,
+    ) -> Result<NotificationResponse, NotificationRequestError>;
+
+    async fn send_template_notification(
+        &self,
+        properties: HashMap<String, String>,
+        tags: Vec<&str>,
+    ) -> Result<NotificationResponse, NotificationRequestError>;
+
+    async fn send_scheduled_notification(
+        &self,
+        request_message: NotificationRequest,
+        schedule_time: DateTime<Utc>,
+        tags: Vec<&str>,
+    ) -> Result<ScheduledNotificationResponse, NotificationRequestError>;
+
+    async fn cancel_scheduled_notification(
+        &self,
+        notification_id: &str,
+    ) -> Result<(), NotificationRequestError>;
+
+    async fn get_notification_telemetry(
+        &self,
+        notification_id: &str,
+    ) -> Result<NotificationTelemetry, NotificationRequestError>;
+}
+
+#[async_trait]
+impl NotificationHub for NotificationHubClient {
+    async fn get_installation(
+        &self,
+        installation_id: &str,
+    ) -> Result<Installation, NotificationRequestError> {
+        NotificationHubClient::get_installation(self, installation_id).await
+    }
+
+    async fn upsert_installation(
+        &self,
+        installation: Installation,
+    ) -> Result<InstallationPathResponse, NotificationRequestError> {
+        NotificationHubClient::upsert_installation(self, installation).await
+    }
+
+    async fn patch_installation(
+        &self,
+        installation_id: &str,
+        patches: Vec<InstallationPatch>,
+    ) -> Result<InstallationPathResponse, NotificationRequestError> {
+        NotificationHubClient::patch_installation(self, installation_id, patches).await
+    }
+
+    async fn list_installations_page(
+        &self,
+        continuation_token: Option<&str>,
+    ) -> Result<InstallationPage, NotificationRequestError> {
+        NotificationHubClient::list_installations_page(self, continuation_token).await
+    }
+
+    async fn delete_installation(
+        &self,
+        installation_id: &str,
+    ) -> Result<(), NotificationRequestError> {
+        NotificationHubClient::delete_installation(self, installation_id).await
+    }
+
+    async fn create_registration(
+        &self,
+        registration: RegistrationDescription,
+    ) -> Result<RegistrationDescription, NotificationRequestError> {
+        NotificationHubClient::create_registration(self, registration).await
+    }
+
+    async fn get_registration(
+        &self,
+        registration_id: &str,
+    ) -> Result<RegistrationDescription, NotificationRequestError> {
+        NotificationHubClient::get_registration(self, registration_id).await
+    }
+
+    async fn update_registration(
+        &self,
+        registration_id: &str,
+        registration: RegistrationDescription,
+    ) -> Result<RegistrationDescription, NotificationRequestError> {
+        NotificationHubClient::update_registration(self, registration_id, registration).await
+    }
+
+    async fn delete_registration(
+        &self,
+        registration_id: &str,
+    ) -> Result<(), NotificationRequestError> {
+        NotificationHubClient::delete_registration(self, registration_id).await
+    }
+
+    async fn send_direct_notification(
+        &self,
+        request_message: NotificationRequest,
+        device_tokens: &[&str],
+    ) -> Result<NotificationResponse, NotificationRequestError> {
+        NotificationHubClient::send_direct_notification(self, request_message, device_tokens).await
+    }
+
+    async fn send_tagged_notification(
+        &self,
+        request_message: NotificationRequest,
+        tags: Vec<&str>,
+    ) -> Result<NotificationResponse, NotificationRequestError> {
+        NotificationHubClient::send_tagged_notification(self, request_message, tags).await
+    }
+
+    async fn send_tag_expression_notification(
+        &self,
+        request_message: NotificationRequest,
+        tag_expression: &str,
+    ) -> Result<NotificationResponse, NotificationRequestError> {
+        NotificationHubClient::send_tag_expression_notification(
+            self,
+            request_message,
+            tag_expression,
+        )
+        .await
+    }
+
+    async fn send_template_notification(
+        &self,
+        properties: HashMap<String, String>,
+        tags: Vec<&str>,
+    ) -> Result<NotificationResponse, NotificationRequestError> {
+        NotificationHubClient::send_template_notification(self, properties, tags).await
+    }
+
+    async fn send_scheduled_notification(
+        &self,
+        request_message: NotificationRequest,
+        schedule_time: DateTime<Utc>,
+        tags: Vec<&str>,
+    ) -> Result<ScheduledNotificationResponse, NotificationRequestError> {
+        NotificationHubClient::send_scheduled_notification(
+            self,
+            request_message,
+            schedule_time,
+            tags,
+        )
+        .await
+    }
+
+    async fn cancel_scheduled_notification(
+        &self,
+        notification_id: &str,
+    ) -> Result<(), NotificationRequestError> {
+        NotificationHubClient::cancel_scheduled_notification(self, notification_id).await
+    }
+
+    async fn get_notification_telemetry(
+        &self,
+        notification_id: &str,
+    ) -> Result<NotificationTelemetry, NotificationRequestError> {
+        NotificationHubClient::get_notification_telemetry(self, notification_id).await
+    }
+}
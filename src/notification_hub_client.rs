@@ -1,16 +1,68 @@
+use crate::azure_error::AzureError;
+use crate::cloud::AzureCloud;
+use crate::config::NotificationHubConfig;
+use crate::connector::BoxedConnector;
+#[cfg(feature = "debug-capture")]
+use crate::debug_capture::DebugExchange;
+use crate::hub_metrics::HubMetrics;
+use crate::notification_hub_job::NotificationHubJob;
+use crate::notification_telemetry::{NotificationTelemetry, TestSendOutcome};
+use crate::platform::Platform;
+use crate::rate_limiter::RateLimiter;
+use crate::registration::{RegistrationDescription, ATOM_ENTRY_CONTENT_TYPE};
+use crate::request_metrics::RequestMetrics;
+use crate::retry_policy::{parse_retry_after, RetryPolicy};
 use crate::sas_token_provider::{GenerateSasTokenError, SasTokenProvider};
-use hyper::body::Buf;
-use hyper::header::{HeaderName, HeaderValue};
-use hyper::header::{AUTHORIZATION, CONTENT_TYPE};
-use hyper::{Body, Client, Request, StatusCode};
-use hyper_tls::HttpsConnector;
+use crate::tag_expression::{any_tag_expression, validate_tag_expression};
+use crate::token_provider::{BearerTokenProvider, TokenProvider, TokenProviderError};
+use crate::wns_notification::WnsType;
+use chrono::{DateTime, SecondsFormat, Utc};
+use futures::stream::{self, Stream, StreamExt, TryStreamExt};
+use hyper::body::Bytes;
+use hyper::client::connect::Connection;
+use hyper::client::HttpConnector;
+use hyper::header::{HeaderMap, HeaderName, HeaderValue};
+#[cfg(feature = "compression")]
+use hyper::header::{ACCEPT_ENCODING, CONTENT_ENCODING};
+use hyper::header::{
+    AUTHORIZATION, CONTENT_LENGTH, CONTENT_TYPE, ETAG, IF_MATCH, IF_NONE_MATCH, LOCATION,
+    RETRY_AFTER, USER_AGENT,
+};
+use hyper::service::Service;
+use hyper::{Body, Client, Method, Request, Response, StatusCode, Uri};
 use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
 use std::collections::HashMap;
+use std::future::Future;
 use std::str;
 use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_util::sync::CancellationToken;
 
-/// The API version to use for any requests
-const API_VERSION: &str = "2017-04";
+/// The API version used for requests unless overridden with `with_api_version`
+pub const DEFAULT_API_VERSION: &str = "2017-04";
+
+/// The `User-Agent` sent with every request unless overridden with `with_user_agent`
+pub const DEFAULT_USER_AGENT: &str =
+    concat!("azure-notificationhubs-rust/", env!("CARGO_PKG_VERSION"));
+
+/// The header Azure uses to return the token for the next page of results
+const CONTINUATION_TOKEN_HEADER: &str = "x-ms-continuationtoken";
+
+/// The number of registrations requested per page by `count_registrations`
+const REGISTRATIONS_PAGE_SIZE: u32 = 100;
+
+/// Separates the notification and device list parts of a direct batch send
+const DIRECT_BATCH_BOUNDARY: &str = "notification-hub-direct-batch";
+
+/// The installation `check_connection` looks up, which is not expected to exist
+const CONNECTION_CHECK_INSTALLATION_ID: &str = "azure-notificationhubs-rust-connection-check";
+
+const PLATFORM_HEADER: &str = "servicebusnotification-format";
+const TRACKING_ID_HEADER: &str = "trackingid";
+const CORRELATION_ID_HEADER: &str = "x-ms-correlation-request-id";
 
 #[allow(missing_docs)]
 #[non_exhaustive]
@@ -24,77 +76,538 @@ pub enum FromConnectionStringError {
     FailedToGetSharedAccessKey,
     #[error("Failed to get the primary key from the given connection string")]
     FailedToGetPrimaryKey,
-    #[error("Generate SAS token error: {0}")]
-    GenerateSasTokenError(GenerateSasTokenError),
+    #[error("No hub name was given and the connection string has no EntityPath")]
+    FailedToGetHubName,
+    #[error("The given hub name conflicts with the connection string's EntityPath")]
+    ConflictingEntityPath,
+    #[error("Failed to generate a SAS token")]
+    GenerateSasTokenError(#[from] GenerateSasTokenError),
+    #[error("The connection string's endpoint does not belong to the configured Azure cloud")]
+    EndpointNotInCloud,
+    #[error("The connection string's endpoint '{0}' is not a URL with a host")]
+    InvalidEndpoint(String),
 }
 
 #[allow(missing_docs)]
 #[non_exhaustive]
 #[derive(Debug, thiserror::Error)]
 pub enum NotificationRequestError {
-    #[error("Hyper request error: {0}")]
-    HttpRequestError(hyper::Error),
-    #[error("Unsuccessful HTTP status code: {0}")]
-    InvalidHttpResponse(StatusCode),
-    #[error("Generate SAS token error: {0}")]
-    GenerateSasTokenError(GenerateSasTokenError),
-    #[error("JSON Serialization Error: {0}")]
-    JsonSerializationError(serde_json::Error),
+    #[error("HTTP transport error")]
+    HttpRequestError(#[from] hyper::Error),
+    #[error("Unsuccessful HTTP status code: {status}: {body}")]
+    InvalidHttpResponse {
+        status: StatusCode,
+        body: String,
+        tracking_id: Option<String>,
+        correlation_id: Option<String>,
+        /// The error parsed from `body`, when Azure returned a recognized format
+        azure_error: Option<AzureError>,
+    },
+    #[error("The requested resource was not found")]
+    NotFound,
+    #[error("Authentication failed with HTTP status code: {status}: {body}")]
+    Unauthorized { status: StatusCode, body: String },
+    #[error("Failed to generate a SAS token")]
+    GenerateSasTokenError(#[from] GenerateSasTokenError),
+    #[error("JSON serialization error")]
+    JsonSerializationError(#[from] serde_json::Error),
+    #[error("The request timed out")]
+    Timeout,
+    #[error("Invalid header: {0}")]
+    InvalidHeader(String),
+    #[error("XML serialization error")]
+    XmlSerializationError(#[from] quick_xml::DeError),
+    #[error("A notification can target either a device handle or a tag expression, not both")]
+    InvalidAudience,
+    #[error("The request was cancelled")]
+    Cancelled,
+    #[error("Failed to deserialize the response body: {message}. Body: {body}")]
+    InvalidResponseBody { message: String, body: String },
+    #[error("Failed to build the HTTP request")]
+    InvalidRequest(#[from] hyper::http::Error),
+    #[error("Invalid tag expression: {0}")]
+    InvalidTagExpression(String),
+    #[error("Azure returned an empty response where a body was expected")]
+    EmptyResponse,
+    #[error("The resource already exists")]
+    AlreadyExists,
+    #[error("A direct send needs at least one device handle")]
+    NoDeviceHandles,
+    #[error("Adding tags needs at least one tag")]
+    NoTags,
+    #[error("Azure did not return a notification ID, which requires the Standard tier")]
+    TelemetryUnavailable,
+    #[error("The notification payload is {size} bytes, over the platform limit of {limit} bytes")]
+    PayloadTooLarge { size: usize, limit: usize },
+    #[error(
+        "Azure redirected the request with {status} to {}",
+        .location.as_deref().unwrap_or("no location")
+    )]
+    Redirect {
+        status: StatusCode,
+        location: Option<String>,
+    },
+    #[error("The token provider failed")]
+    TokenProviderError(#[source] TokenProviderError),
+}
+
+impl NotificationRequestError {
+    /// Whether the request failed before reaching Azure, while resolving the
+    /// host or establishing the connection. Such requests are safe to retry.
+    pub fn is_connect(&self) -> bool {
+        matches!(self, NotificationRequestError::HttpRequestError(err) if err.is_connect())
+    }
+
+    /// Whether the request or its connection timed out.
+    pub fn is_timeout(&self) -> bool {
+        matches!(self, NotificationRequestError::Timeout)
+    }
+
+    /// Whether Azure's response could not be understood at the HTTP level,
+    /// which retrying is not expected to fix.
+    pub fn is_protocol(&self) -> bool {
+        matches!(
+            self,
+            NotificationRequestError::HttpRequestError(err)
+                if err.is_parse() || err.is_parse_status() || err.is_incomplete_message()
+        )
+    }
 }
 
 #[derive(Clone, Debug, Default)]
 pub struct NotificationRequest {
     pub headers: HashMap<String, String>,
-    pub message: String,
+    pub message: Bytes,
     pub content_type: String,
-    pub platform: String,
+    pub platform: Platform,
+}
+
+impl NotificationRequest {
+    /// Creates a request from any payload convertible to `Bytes`, such as a
+    /// `String`, a `Vec<u8>`, or an already serialized buffer.
+    pub fn new(
+        platform: impl Into<Platform>,
+        content_type: impl Into<String>,
+        message: impl Into<Bytes>,
+    ) -> Self {
+        Self {
+            headers: HashMap::new(),
+            message: message.into(),
+            content_type: content_type.into(),
+            platform: platform.into(),
+        }
+    }
+
+    /// An APNs alert with a ready-made JSON payload, sent at high priority.
+    /// Use `ApnsNotification` to build the payload or pick other headers.
+    pub fn apple(json: impl Into<Bytes>) -> Self {
+        let mut request = Self::with_default_content_type(Platform::Apple, json);
+        request.headers = HashMap::from([
+            ("apns-push-type".to_string(), "alert".to_string()),
+            ("apns-priority".to_string(), "10".to_string()),
+        ]);
+        request
+    }
+
+    /// A legacy FCM notification with a ready-made JSON payload.
+    pub fn fcm(json: impl Into<Bytes>) -> Self {
+        Self::with_default_content_type(Platform::Fcm, json)
+    }
+
+    /// An FCM v1 notification with a ready-made JSON payload, which must be
+    /// wrapped in a `message` object.
+    pub fn fcm_v1(json: impl Into<Bytes>) -> Self {
+        Self::with_default_content_type(Platform::FcmV1, json)
+    }
+
+    /// A WNS notification with a ready-made XML payload, or any bytes for a
+    /// raw notification.
+    pub fn windows(payload: impl Into<Bytes>, wns_type: WnsType) -> Self {
+        let content_type = match wns_type {
+            WnsType::Raw => "application/octet-stream",
+            WnsType::Toast | WnsType::Tile => Platform::Windows.default_content_type().unwrap(),
+        };
+
+        let mut request = Self::new(Platform::Windows, content_type, payload);
+        request.headers.insert(
+            "X-WNS-Type".to_string(),
+            wns_type.as_header_value().to_string(),
+        );
+        request
+    }
+
+    /// A template notification whose JSON payload holds the template properties.
+    pub fn template(json: impl Into<Bytes>) -> Self {
+        Self::with_default_content_type(Platform::Template, json)
+    }
+
+    fn with_default_content_type(platform: Platform, message: impl Into<Bytes>) -> Self {
+        let content_type = platform.default_content_type().unwrap_or_default();
+        Self::new(platform, content_type, message)
+    }
+
+    /// Sends the request with a `TrackingId` header carrying a
+    /// caller-generated identifier, such as a UUID. Azure records the send
+    /// under that ID instead of generating one, and echoes it back as
+    /// `NotificationResponse::tracking_id`.
+    ///
+    /// Azure does not reject a second send with the same ID, so this does not
+    /// deduplicate on its own. It does let a caller who retries after an
+    /// ambiguous timeout match the attempts up in its own records and in Azure
+    /// support logs. Retries made under a retry policy reuse the same ID.
+    pub fn with_tracking_id(mut self, tracking_id: impl Into<String>) -> Self {
+        self.headers
+            .retain(|name, _| !name.eq_ignore_ascii_case(TRACKING_ID_HEADER));
+        self.headers
+            .insert(TRACKING_ID_HEADER.to_string(), tracking_id.into());
+        self
+    }
+
+    /// The identifier set with `with_tracking_id`, if any.
+    pub fn tracking_id(&self) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(TRACKING_ID_HEADER))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Replaces the payload with the given text.
+    pub fn set_message_text(&mut self, message: String) {
+        self.message = Bytes::from(message);
+    }
+
+    /// The payload as text, with any invalid UTF-8 replaced.
+    pub fn message_text(&self) -> Cow<'_, str> {
+        String::from_utf8_lossy(&self.message)
+    }
 }
 
 #[derive(Clone, Debug, Default)]
 pub struct NotificationResponse {
-    pub tracking_id: String,
-    pub correlation_id: String,
+    /// The `TrackingId` Azure recorded the send under: the one given with
+    /// `NotificationRequest::with_tracking_id`, or one Azure generated
+    pub tracking_id: Option<String>,
+    pub correlation_id: Option<String>,
+    /// The telemetry resource for the notification, `.../messages/{id}`
+    pub location: Option<String>,
+    pub notification_id: Option<String>,
+    /// The remaining request quota Azure reported, if any
+    pub rate_limit: Option<RateLimitHint>,
+    /// Every header of the response, including ones not surfaced above such
+    /// as `x-ms-request-id`
+    pub headers: HeaderMap,
+}
+
+/// Quota information Azure attaches to some responses, from the
+/// `x-ms-ratelimit-remaining` and `x-ms-ratelimit-reset` headers (or their
+/// `x-ratelimit-` forms) and `Retry-After`. Not every tier or operation
+/// reports it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RateLimitHint {
+    /// How many more requests are allowed before the quota resets
+    pub remaining: Option<u64>,
+    pub reset_after: Option<Duration>,
+}
+
+impl RateLimitHint {
+    /// Reads the hint from a response, treating a `429` as an exhausted quota.
+    pub(crate) fn from_response(res: &Response<Body>) -> Option<Self> {
+        let headers = res.headers();
+        let header = |names: &[&str]| {
+            names
+                .iter()
+                .find_map(|name| headers.get(*name))
+                .and_then(|value| value.to_str().ok())
+        };
+
+        // Resource-specific forms such as `x-ms-ratelimit-remaining-subscription-reads`
+        // each count down a separate quota, so the smallest one applies.
+        let remaining = headers
+            .iter()
+            .filter(|(name, _)| {
+                let name = name.as_str();
+                name == "x-ratelimit-remaining" || name.starts_with("x-ms-ratelimit-remaining")
+            })
+            .filter_map(|(_, value)| value.to_str().ok()?.trim().parse::<u64>().ok())
+            .min()
+            .or((res.status() == StatusCode::TOO_MANY_REQUESTS).then_some(0));
+        let reset_after = header(&["x-ms-ratelimit-reset", "x-ratelimit-reset"])
+            .and_then(|value| value.trim().parse::<u64>().ok())
+            .map(Duration::from_secs)
+            .or_else(|| header(&[RETRY_AFTER.as_str()]).and_then(parse_retry_after));
+
+        if remaining.is_none() && reset_after.is_none() {
+            return None;
+        }
+
+        Some(Self {
+            remaining,
+            reset_after,
+        })
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct ScheduledNotificationResponse {
+    pub notification_id: String,
+    pub location: String,
 }
 
+#[derive(Clone)]
 pub struct NotificationHubClient {
     hub_name: String,
     host_name: String,
-    token_provider: SasTokenProvider,
+    https_host: String,
+    credential: Credential,
+    http_client: HttpsClient,
+    /// The connector set with `with_connector`, kept so that the client can be
+    /// rebuilt around it
+    connector: Option<BoxedConnector>,
+    retry_policy: Option<RetryPolicy>,
+    api_version: String,
+    request_timeout: Option<Duration>,
+    check_payload_size: bool,
+    user_agent: String,
+    connect_timeout: Option<Duration>,
+    http2: bool,
+    rate_limiter: Option<Arc<RateLimiter>>,
+    #[cfg(feature = "danger-accept-invalid-certs")]
+    accept_invalid_certs: bool,
+    #[cfg(feature = "compression")]
+    gzip_requests: bool,
+    on_request_complete: Option<RequestCallback>,
+    max_redirects: usize,
+    cancellation_token: Option<CancellationToken>,
+    #[cfg(feature = "debug-capture")]
+    debug_capture: Option<DebugCaptureCallback>,
+}
+
+type RequestCallback = Arc<dyn Fn(RequestMetrics) + Send + Sync>;
+
+/// Where the `Authorization` header of each request comes from.
+#[derive(Clone)]
+enum Credential {
+    Sas(SasTokenProvider),
+    Custom(Arc<dyn TokenProvider>),
 }
+#[cfg(feature = "debug-capture")]
+type DebugCaptureCallback = Arc<dyn Fn(&DebugExchange) + Send + Sync>;
 
 #[derive(Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Installation {
     pub installation_id: String,
-    pub user_id: String,
-    pub last_active_on: String,
-    pub expiration_time: String,
-    pub last_update: String,
-    pub platform: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user_id: Option<String>,
+    #[serde(
+        default,
+        with = "azure_date_time",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub last_active_on: Option<DateTime<Utc>>,
+    #[serde(
+        default,
+        with = "azure_date_time",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub expiration_time: Option<DateTime<Utc>>,
+    #[serde(
+        default,
+        with = "azure_date_time",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub last_update: Option<DateTime<Utc>>,
+    #[serde(with = "crate::platform::installation")]
+    pub platform: Platform,
     pub push_channel: String,
+    #[serde(default)]
     pub expired_push_channel: bool,
+    #[serde(default)]
     pub tags: Vec<String>,
+    #[serde(default)]
     pub templates: HashMap<String, InstallationTemplate>,
+    /// The version Azure returned when the installation was read, for use
+    /// with `upsert_installation_if_match`
+    #[serde(skip)]
+    pub etag: Option<String>,
+}
+
+impl Installation {
+    /// The `expiration_time`, kept for callers written before it was parsed
+    /// on deserialization.
+    pub fn expiration_date(&self) -> Option<DateTime<Utc>> {
+        self.expiration_time
+    }
+
+    /// Whether Azure has flagged the push channel as expired or the
+    /// installation's expiration time has passed.
+    pub fn is_expired(&self) -> bool {
+        self.expired_push_channel
+            || self
+                .expiration_date()
+                .is_some_and(|expiration| expiration <= Utc::now())
+    }
+
+    /// Adds a tag locally, returning `false` if the installation already had it.
+    pub fn add_tag(&mut self, tag: impl Into<String>) -> bool {
+        let tag = tag.into();
+        if self.has_tag(&tag) {
+            return false;
+        }
+        self.tags.push(tag);
+        true
+    }
+
+    /// Removes a tag locally, returning `false` if the installation did not have it.
+    pub fn remove_tag(&mut self, tag: &str) -> bool {
+        let len = self.tags.len();
+        self.tags.retain(|existing| existing != tag);
+        self.tags.len() != len
+    }
+
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tags.iter().any(|existing| existing == tag)
+    }
 }
 
 #[derive(Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct InstallationTemplate {
     pub body: String,
+    #[serde(default)]
     pub headers: HashMap<String, String>,
+    #[serde(default)]
     pub tags: Vec<String>,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct InstallationPatch {
     op: String,
     path: String,
-    value: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    value: Option<String>,
+}
+
+impl InstallationPatch {
+    /// A JSON Patch `add` operation, e.g. `add("/tags", "sports")`.
+    pub fn add(path: impl Into<String>, value: impl Into<String>) -> Self {
+        Self {
+            op: "add".to_string(),
+            path: path.into(),
+            value: Some(value.into()),
+        }
+    }
+
+    /// A JSON Patch `replace` operation, e.g. `replace("/pushChannel", token)`.
+    pub fn replace(path: impl Into<String>, value: impl Into<String>) -> Self {
+        Self {
+            op: "replace".to_string(),
+            path: path.into(),
+            value: Some(value.into()),
+        }
+    }
+
+    /// A JSON Patch `remove` operation, e.g. `remove("/tags/sports")`.
+    pub fn remove(path: impl Into<String>) -> Self {
+        Self {
+            op: "remove".to_string(),
+            path: path.into(),
+            value: None,
+        }
+    }
+
+    /// Adds a single tag to the installation.
+    pub fn add_tag(tag: impl Into<String>) -> Self {
+        Self::add("/tags", tag)
+    }
+
+    /// Removes a single tag from the installation. The tag is escaped as a
+    /// JSON Pointer token, so tags containing `/` or `~` are removed whole.
+    pub fn remove_tag(tag: &str) -> Self {
+        Self::remove(format!("/tags/{}", json_pointer_token(tag)))
+    }
+
+    /// Replaces all of the installation's tags. Azure takes the new tags as a
+    /// JSON array serialized into the operation's value.
+    pub fn replace_tags(tags: &[&str]) -> Self {
+        let tags = serde_json::to_string(tags).expect("a list of strings always serializes");
+        Self::replace("/tags", tags)
+    }
+}
+
+/// Escapes `~` and `/` in a JSON Pointer reference token, per RFC 6901.
+fn json_pointer_token(token: &str) -> String {
+    token.replace('~', "~0").replace('/', "~1")
+}
+
+pub struct InstallationPage {
+    pub installations: Vec<Installation>,
+    pub continuation_token: Option<String>,
 }
 
 pub struct InstallationPathResponse {
     pub content_location: String,
+    /// The installation ID parsed from `content_location`
+    pub installation_id: Option<String>,
+    pub etag: Option<String>,
+}
+
+/// The settings read from a Notification Hubs connection string.
+pub(crate) struct ConnectionStringParts<'a> {
+    pub(crate) endpoint: &'a str,
+    pub(crate) sas_key_name: &'a str,
+    pub(crate) sas_key_value: &'a str,
+    pub(crate) entity_path: Option<&'a str>,
+}
+
+impl<'a> ConnectionStringParts<'a> {
+    /// Parses `Key=Value` segments separated by `;`. Keys are matched
+    /// case-insensitively in any order, and empty or unknown segments are ignored.
+    pub(crate) fn parse(connection_string: &'a str) -> Result<Self, FromConnectionStringError> {
+        let mut endpoint: Option<&str> = None;
+        let mut sas_key_name: Option<&str> = None;
+        let mut sas_key_value: Option<&str> = None;
+        let mut entity_path: Option<&str> = None;
+
+        for segment in connection_string.split(';') {
+            let segment = segment.trim();
+            if segment.is_empty() {
+                continue;
+            }
+
+            let (key, value) = segment
+                .split_once('=')
+                .ok_or(FromConnectionStringError::InvalidError)?;
+            let key = key.trim();
+            let value = value.trim();
+
+            if key.eq_ignore_ascii_case("Endpoint") {
+                endpoint = Some(value);
+            } else if key.eq_ignore_ascii_case("SharedAccessKeyName") {
+                sas_key_name = Some(value);
+            } else if key.eq_ignore_ascii_case("SharedAccessKey") {
+                sas_key_value = Some(value);
+            } else if key.eq_ignore_ascii_case("EntityPath") {
+                entity_path = Some(value);
+            }
+        }
+
+        let endpoint = endpoint.ok_or(FromConnectionStringError::FailedToGetHostname)?;
+        if !url::Url::parse(endpoint).is_ok_and(|url| url.has_host()) {
+            return Err(FromConnectionStringError::InvalidEndpoint(
+                endpoint.to_string(),
+            ));
+        }
+
+        Ok(Self {
+            endpoint,
+            sas_key_name: sas_key_name
+                .ok_or(FromConnectionStringError::FailedToGetSharedAccessKey)?,
+            sas_key_value: sas_key_value.ok_or(FromConnectionStringError::FailedToGetPrimaryKey)?,
+            entity_path: entity_path.filter(|entity_path| !entity_path.is_empty()),
+        })
+    }
 }
 
 impl NotificationHubClient {
@@ -102,140 +615,481 @@ impl NotificationHubClient {
         connection_string: &str,
         hub_name: &str,
     ) -> Result<NotificationHubClient, FromConnectionStringError> {
-        let parts: Vec<&str> = connection_string.split(';').collect();
-        let mut host_name: Option<&str> = None;
-        let mut sas_key_name: Option<&str> = None;
-        let mut sas_key_value: Option<&str> = None;
+        let parts = ConnectionStringParts::parse(connection_string)?;
+        Ok(Self::from_parts(&parts, hub_name))
+    }
 
-        if parts.len() != 3 {
-            return Err(FromConnectionStringError::InvalidError);
-        }
+    /// Creates a client whose hub name comes from the connection string's
+    /// `EntityPath`. An explicit `hub_name` may be given instead, but it is an
+    /// error for it to disagree with an `EntityPath` that is also present.
+    pub fn from_connection_string_with_entity_path(
+        connection_string: &str,
+        hub_name: Option<&str>,
+    ) -> Result<NotificationHubClient, FromConnectionStringError> {
+        let parts = ConnectionStringParts::parse(connection_string)?;
+        let hub_name = match (parts.entity_path, hub_name) {
+            (Some(entity_path), Some(hub_name)) if entity_path != hub_name => {
+                return Err(FromConnectionStringError::ConflictingEntityPath)
+            }
+            (Some(entity_path), _) => entity_path,
+            (None, Some(hub_name)) => hub_name,
+            (None, None) => return Err(FromConnectionStringError::FailedToGetHubName),
+        };
 
-        for val in parts.iter() {
-            let start = match val.find('=') {
-                Some(size) => size + 1,
-                None => continue,
-            };
+        Ok(Self::from_parts(&parts, hub_name))
+    }
 
-            if val.contains("Endpoint=") {
-                host_name = Some(&val[start..]);
-            }
+    /// Starts configuring a client for the given hub; see `NotificationHubClientBuilder`.
+    pub fn builder(connection_string: &str, hub_name: &str) -> NotificationHubClientBuilder {
+        NotificationHubClientBuilder::new(connection_string, hub_name)
+    }
 
-            if val.contains("SharedAccessKeyName=") {
-                sas_key_name = Some(&val[start..]);
-            }
+    fn from_parts(parts: &ConnectionStringParts, hub_name: &str) -> Self {
+        let token_provider =
+            SasTokenProvider::new(parts.sas_key_name.into(), parts.sas_key_value.into());
 
-            if val.contains("SharedAccessKey=") {
-                sas_key_value = Some(&val[start..]);
-            }
+        Self::from_token_provider(token_provider, parts.endpoint, hub_name)
+    }
+
+    /// Creates a client with the settings in `config`, leaving the defaults in
+    /// place for any it does not set.
+    pub fn with_config(
+        connection_string: &str,
+        hub_name: &str,
+        config: NotificationHubConfig,
+    ) -> Result<NotificationHubClient, FromConnectionStringError> {
+        let mut builder = Self::builder(connection_string, hub_name);
+
+        if let Some(timeout) = config.timeout {
+            builder = builder.timeout(timeout);
+        }
+        if let Some(timeout) = config.connect_timeout {
+            builder = builder.connect_timeout(timeout);
+        }
+        if let Some(retry_policy) = config.retry_policy {
+            builder = builder.retry_policy(retry_policy);
+        }
+        if let Some(requests_per_second) = config.rate_limit {
+            builder = builder.rate_limit(requests_per_second);
+        }
+        if let Some(api_version) = &config.api_version {
+            builder = builder.api_version(api_version);
+        }
+        if let Some(user_agent) = &config.user_agent {
+            builder = builder.user_agent(user_agent);
+        }
+        if let Some(enabled) = config.http2 {
+            builder = builder.http2(enabled);
         }
 
-        let host_name = host_name.ok_or(FromConnectionStringError::FailedToGetHostname)?;
-        let sas_key_name =
-            sas_key_name.ok_or(FromConnectionStringError::FailedToGetSharedAccessKey)?;
-        let sas_key_value =
-            sas_key_value.ok_or(FromConnectionStringError::FailedToGetPrimaryKey)?;
-        let token_provider = SasTokenProvider {
-            sas_key_name: sas_key_name.into(),
-            sas_key_value: sas_key_value.into(),
-        };
+        builder.build()
+    }
 
-        Ok(Self {
+    /// Creates a client from an explicit token provider, for keys managed
+    /// outside a connection string. `host_name` is the namespace endpoint,
+    /// e.g. `sb://<namespace>.servicebus.windows.net/`.
+    pub fn from_token_provider(
+        token_provider: SasTokenProvider,
+        host_name: &str,
+        hub_name: &str,
+    ) -> Self {
+        Self::from_credential(Credential::Sas(token_provider), host_name, hub_name)
+    }
+
+    /// Creates a client whose requests are authorized by `token_provider`
+    /// instead of a shared access key. `host_name` is the namespace endpoint,
+    /// e.g. `sb://<namespace>.servicebus.windows.net/`.
+    pub fn from_custom_token_provider(
+        token_provider: impl TokenProvider + 'static,
+        host_name: &str,
+        hub_name: &str,
+    ) -> Self {
+        Self::from_credential(
+            Credential::Custom(Arc::new(token_provider)),
+            host_name,
+            hub_name,
+        )
+    }
+
+    /// Creates a client that authorizes requests with Azure Active Directory
+    /// (Microsoft Entra ID) access tokens instead of a shared access key.
+    /// `token` is awaited before every request; see `BearerTokenProvider`.
+    pub fn from_bearer_token<F, Fut>(token: F, host_name: &str, hub_name: &str) -> Self
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<String, TokenProviderError>> + Send + 'static,
+    {
+        Self::from_custom_token_provider(BearerTokenProvider::new(token), host_name, hub_name)
+    }
+
+    fn from_credential(credential: Credential, host_name: &str, hub_name: &str) -> Self {
+        let http_client = build_https_client(None, true, false);
+
+        Self {
             hub_name: hub_name.to_string(),
             host_name: host_name.to_string(),
-            token_provider,
-        })
+            https_host: https_endpoint(host_name),
+            credential,
+            http_client,
+            connector: None,
+            retry_policy: None,
+            api_version: DEFAULT_API_VERSION.to_string(),
+            request_timeout: None,
+            check_payload_size: true,
+            user_agent: DEFAULT_USER_AGENT.to_string(),
+            connect_timeout: None,
+            http2: true,
+            rate_limiter: None,
+            #[cfg(feature = "danger-accept-invalid-certs")]
+            accept_invalid_certs: false,
+            #[cfg(feature = "compression")]
+            gzip_requests: false,
+            on_request_complete: None,
+            max_redirects: 0,
+            cancellation_token: None,
+            #[cfg(feature = "debug-capture")]
+            debug_capture: None,
+        }
+    }
+
+    pub(crate) fn with_hub_name(mut self, hub_name: &str) -> Self {
+        self.hub_name = hub_name.to_string();
+        self
+    }
+
+    /// The URI of a resource directly under the namespace, such as a hub.
+    pub(crate) fn namespace_uri(&self, path: &str) -> String {
+        format!(
+            "{}/{}?api-version={}",
+            &self.https_host, path, &self.api_version
+        )
+    }
+
+    /// Sets how long the SAS tokens attached to each request remain valid.
+    /// Has no effect on a client created with `from_custom_token_provider`.
+    pub fn with_sas_token_ttl(mut self, ttl: chrono::Duration) -> Self {
+        if let Credential::Sas(token_provider) = &mut self.credential {
+            token_provider.set_ttl(ttl);
+        }
+        self
+    }
+
+    /// Opts in to retrying requests that Azure throttles or rejects as
+    /// temporarily unavailable.
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = Some(retry_policy);
+        self
+    }
+
+    /// Sets the `api-version` query parameter sent with every request.
+    pub fn with_api_version(mut self, api_version: &str) -> Self {
+        self.api_version = api_version.to_string();
+        self
+    }
+
+    /// Bounds how long establishing a connection to Azure may take. It also
+    /// applies to a connector set with `with_connector`, in either order.
+    pub fn with_connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = Some(timeout);
+        self.http_client = self.build_https_client();
+        self
+    }
+
+    /// Enables or disables negotiating HTTP/2 with Azure, which lets concurrent
+    /// requests share one connection. Enabled by default; disable it to force
+    /// HTTP/1.1.
+    ///
+    /// HTTP/2 is negotiated through ALPN, which only the `rustls` backend
+    /// supports: with `native-tls`, connections always use HTTP/1.1 and this
+    /// setting has no effect. A connector set with `with_connector` is kept,
+    /// and negotiates the protocol itself.
+    pub fn with_http2(mut self, enabled: bool) -> Self {
+        self.http2 = enabled;
+        self.http_client = self.build_https_client();
+        self
+    }
+
+    /// Disables verification of Azure's TLS certificate and host name.
+    ///
+    /// **This removes all protection against impersonation and interception.**
+    /// It exists only for tests against a local mock or a MITM debugging proxy
+    /// with a self-signed certificate, and must never be enabled when talking
+    /// to a real hub. It has no effect on a connector set with
+    /// `with_connector`, which is responsible for TLS.
+    #[cfg(feature = "danger-accept-invalid-certs")]
+    pub fn with_danger_accept_invalid_certs(mut self, enabled: bool) -> Self {
+        self.accept_invalid_certs = enabled;
+        self.http_client = self.build_https_client();
+        self
+    }
+
+    /// Compresses request bodies with gzip and sends them with
+    /// `Content-Encoding: gzip`. Disabled by default. Responses are
+    /// decompressed whenever the `compression` feature is enabled, regardless
+    /// of this setting.
+    #[cfg(feature = "compression")]
+    pub fn with_gzip_requests(mut self, enabled: bool) -> Self {
+        self.gzip_requests = enabled;
+        self
+    }
+
+    fn build_https_client(&self) -> HttpsClient {
+        if let Some(connector) = &self.connector {
+            let connector = connector.clone().with_connect_timeout(self.connect_timeout);
+            return Client::builder().build::<_, Body>(connector);
+        }
+
+        #[cfg(feature = "danger-accept-invalid-certs")]
+        let accept_invalid_certs = self.accept_invalid_certs;
+        #[cfg(not(feature = "danger-accept-invalid-certs"))]
+        let accept_invalid_certs = false;
+
+        build_https_client(self.connect_timeout, self.http2, accept_invalid_certs)
+    }
+
+    /// Routes all traffic through a custom hyper connector, such as one that
+    /// tunnels through an HTTP proxy or presents a client certificate.
+    ///
+    /// The connector is responsible for TLS, so it must be able to reach
+    /// `https://` endpoints. It is kept by later `with_connect_timeout` and
+    /// `with_http2` calls.
+    pub fn with_connector<C>(mut self, connector: C) -> Self
+    where
+        C: Service<Uri> + Clone + Send + Sync + 'static,
+        C::Response: AsyncRead + AsyncWrite + Connection + Send + Unpin + 'static,
+        C::Future: Send + 'static,
+        C::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+    {
+        self.connector = Some(BoxedConnector::new(connector));
+        self.http_client = self.build_https_client();
+        self
+    }
+
+    /// Bounds how long each request may take to receive a response.
+    pub fn with_request_timeout(mut self, timeout: Duration) -> Self {
+        self.request_timeout = Some(timeout);
+        self
+    }
+
+    /// Sets the `User-Agent` header sent with every request.
+    pub fn with_user_agent(mut self, user_agent: &str) -> Self {
+        self.user_agent = user_agent.to_string();
+        self
+    }
+
+    /// Limits outgoing requests to `requests_per_second`, allowing bursts of up
+    /// to that many. Requests wait for capacity instead of provoking throttling
+    /// from Azure. The limit is shared by every clone of the client.
+    ///
+    /// The limiter also adapts to any `RateLimitHint` Azure sends back: it
+    /// stops bursting past the remaining quota, and pauses until the reset
+    /// time once the quota is used up or a request is throttled.
+    pub fn with_rate_limit(mut self, requests_per_second: u32) -> Self {
+        self.rate_limiter = Some(Arc::new(RateLimiter::new(requests_per_second)));
+        self
+    }
+
+    /// Registers a callback that is handed the timing, status and size of
+    /// every request once it completes, for feeding a metrics library. It is
+    /// called on the task that made the request, so it should return quickly.
+    pub fn with_on_request_complete(
+        mut self,
+        callback: impl Fn(RequestMetrics) + Send + Sync + 'static,
+    ) -> Self {
+        self.on_request_complete = Some(Arc::new(callback));
+        self
+    }
+
+    /// Registers a callback that is handed a copy of every request exactly as
+    /// sent, and of the response received, for troubleshooting. SAS
+    /// signatures are masked. Request and response bodies are buffered in
+    /// memory to take the copies, including those of streamed sends.
+    #[cfg(feature = "debug-capture")]
+    pub fn with_debug_capture(
+        mut self,
+        callback: impl Fn(&DebugExchange) + Send + Sync + 'static,
+    ) -> Self {
+        self.debug_capture = Some(Arc::new(callback));
+        self
+    }
+
+    /// Follows up to `max_redirects` redirects to another path on the same
+    /// host, as Azure sends when a resource moves. Only `307` and `308` are
+    /// followed for requests other than `GET`, since they keep the method and
+    /// body. Redirects are not followed by default, and any that are not
+    /// followed fail with `NotificationRequestError::Redirect`.
+    pub fn with_max_redirects(mut self, max_redirects: usize) -> Self {
+        self.max_redirects = max_redirects;
+        self
+    }
+
+    /// Abandons every request made through this client, including any wait
+    /// between retries, with `Cancelled` once `cancellation_token` is
+    /// cancelled. Clone the client to scope a token to a group of calls.
+    pub fn with_cancellation_token(mut self, cancellation_token: CancellationToken) -> Self {
+        self.cancellation_token = Some(cancellation_token);
+        self
+    }
+
+    /// Enables or disables rejecting notifications whose payload exceeds the
+    /// platform's size limit before they are sent. Enabled by default.
+    pub fn with_payload_size_check(mut self, enabled: bool) -> Self {
+        self.check_payload_size = enabled;
+        self
+    }
+
+    /// Verifies the credentials and network path to the hub by reading an
+    /// installation that should not exist. A `404 Not Found` proves the request
+    /// was authenticated, so it is reported as success; rejected credentials
+    /// surface as `NotificationRequestError::Unauthorized`.
+    pub async fn check_connection(&self) -> Result<(), NotificationRequestError> {
+        let uri = format!(
+            "{}/{}/installations/{}?api-version={}",
+            &self.https_host, &self.hub_name, CONNECTION_CHECK_INSTALLATION_ID, &self.api_version
+        );
+
+        let res = self
+            .execute(Method::GET, &uri, HeaderMap::new(), Bytes::new())
+            .await?;
+        match res.status() {
+            StatusCode::OK | StatusCode::NOT_FOUND => Ok(()),
+            _ => Err(error_from_response(res).await),
+        }
     }
 
     pub async fn get_installation(
         &self,
         installation_id: &str,
     ) -> Result<Installation, NotificationRequestError> {
-        let https_host = self.host_name.replace("sb://", "https://");
         let uri = format!(
             "{}/{}/installations/{}?api-version={}",
-            &https_host, &self.hub_name, installation_id, API_VERSION
+            &self.https_host,
+            &self.hub_name,
+            urlencoding::encode(installation_id),
+            &self.api_version
         );
 
-        let mut request = Request::get(uri);
+        let res = self
+            .execute(Method::GET, &uri, HeaderMap::new(), Bytes::new())
+            .await?;
+        if res.status() != StatusCode::OK {
+            return Err(error_from_response(res).await);
+        }
 
-        let sas_token = self
-            .token_provider
-            .generate_sas_token(&self.host_name)
-            .map_err(NotificationRequestError::GenerateSasTokenError)?;
-        let sas_token_header = HeaderValue::from_str(&sas_token).unwrap();
-        request = request.header(AUTHORIZATION, sas_token_header);
+        let etag = header_string(&res, ETAG.as_str());
+        let body = read_expected_body(res).await?;
+        let mut installation: Installation =
+            serde_json::from_slice(&body).map_err(|err| invalid_response_body(err, &body))?;
+        installation.etag = etag;
 
-        let https = HttpsConnector::new();
-        let client = Client::builder().build::<_, hyper::Body>(https);
+        Ok(installation)
+    }
 
-        let request = request.body(Body::empty()).unwrap();
+    /// Checks whether an installation exists without deserializing it.
+    pub async fn installation_exists(
+        &self,
+        installation_id: &str,
+    ) -> Result<bool, NotificationRequestError> {
+        let uri = format!(
+            "{}/{}/installations/{}?api-version={}",
+            &self.https_host,
+            &self.hub_name,
+            urlencoding::encode(installation_id),
+            &self.api_version
+        );
 
-        let res = client
-            .request(request)
-            .await
-            .map_err(NotificationRequestError::HttpRequestError)?;
-        if res.status() != StatusCode::OK {
-            return Err(NotificationRequestError::InvalidHttpResponse(res.status()));
+        let res = self
+            .execute(Method::GET, &uri, HeaderMap::new(), Bytes::new())
+            .await?;
+        match res.status() {
+            StatusCode::OK => Ok(true),
+            StatusCode::NOT_FOUND => Ok(false),
+            _ => Err(error_from_response(res).await),
         }
+    }
 
-        let body = hyper::body::aggregate(res)
-            .await
-            .map_err(NotificationRequestError::HttpRequestError)?;
-        let installation: Installation = serde_json::from_reader(body.reader())
-            .map_err(NotificationRequestError::JsonSerializationError)?;
+    pub async fn upsert_installation(
+        &self,
+        installation: Installation,
+    ) -> Result<InstallationPathResponse, NotificationRequestError> {
+        self.put_installation(installation, HeaderMap::new()).await
+    }
 
-        Ok(installation)
+    /// Upserts an installation only if it is unchanged since it was read with
+    /// the given ETag. A concurrent change fails with status `412 Precondition
+    /// Failed`.
+    pub async fn upsert_installation_if_match(
+        &self,
+        installation: Installation,
+        etag: &str,
+    ) -> Result<InstallationPathResponse, NotificationRequestError> {
+        let mut headers = HeaderMap::new();
+        let etag = HeaderValue::from_str(etag)
+            .map_err(|_| NotificationRequestError::InvalidHeader(IF_MATCH.to_string()))?;
+        headers.insert(IF_MATCH, etag);
+
+        self.put_installation(installation, headers).await
     }
 
-    pub async fn upsert_installation(
+    /// Creates the installation only if none with its ID exists yet, failing
+    /// with `NotificationRequestError::AlreadyExists` instead of overwriting it.
+    pub async fn upsert_installation_if_absent(
+        &self,
+        installation: Installation,
+    ) -> Result<InstallationPathResponse, NotificationRequestError> {
+        let mut headers = HeaderMap::new();
+        headers.insert(IF_NONE_MATCH, HeaderValue::from_static("*"));
+
+        match self.put_installation(installation, headers).await {
+            Err(NotificationRequestError::InvalidHttpResponse {
+                status: StatusCode::PRECONDITION_FAILED | StatusCode::CONFLICT,
+                ..
+            }) => Err(NotificationRequestError::AlreadyExists),
+            result => result,
+        }
+    }
+
+    async fn put_installation(
         &self,
         installation: Installation,
+        mut headers: HeaderMap,
     ) -> Result<InstallationPathResponse, NotificationRequestError> {
         let installation_json = serde_json::to_string(&installation)
             .map_err(NotificationRequestError::JsonSerializationError)?;
         let installation_id = installation.installation_id;
-        let https_host = self.host_name.replace("sb://", "https://");
         let uri = format!(
             "{}/{}/installations/{}?api-version={}",
-            &https_host, &self.hub_name, installation_id, API_VERSION
+            &self.https_host,
+            &self.hub_name,
+            urlencoding::encode(&installation_id),
+            &self.api_version
         );
 
-        let mut request = Request::put(uri);
-
-        let sas_token = self
-            .token_provider
-            .generate_sas_token(&self.host_name)
-            .map_err(NotificationRequestError::GenerateSasTokenError)?;
-        let sas_token_header = HeaderValue::from_str(&sas_token).unwrap();
-        request = request.header(AUTHORIZATION, sas_token_header);
-
-        let content_type = HeaderValue::from_str("application/json").unwrap();
-        request = request.header(CONTENT_TYPE, content_type);
-
-        let request = request.body(Body::from(installation_json)).unwrap();
-
-        let https = HttpsConnector::new();
-        let client = Client::builder().build::<_, hyper::Body>(https);
+        let content_type = HeaderValue::from_static("application/json");
+        headers.insert(CONTENT_TYPE, content_type);
 
-        let res = client
-            .request(request)
-            .await
-            .map_err(NotificationRequestError::HttpRequestError)?;
+        let res = self
+            .execute(Method::PUT, &uri, headers, Bytes::from(installation_json))
+            .await?;
         if res.status() != StatusCode::OK {
-            return Err(NotificationRequestError::InvalidHttpResponse(res.status()));
-        }
-
-        let mut content_location: Option<&str> = None;
-        if res.headers().contains_key("content-location") {
-            content_location = Some(res.headers()["content-location"].to_str().unwrap());
+            return Err(error_from_response(res).await);
         }
 
-        let content_location = content_location.get_or_insert("");
+        Ok(installation_path_response(&res))
+    }
 
-        Ok(InstallationPathResponse {
-            content_location: content_location.to_string(),
-        })
+    /// Upserts many installations concurrently, issuing at most `concurrency`
+    /// requests at a time. Results are returned in the same order as the input.
+    pub async fn upsert_installations(
+        &self,
+        installations: Vec<Installation>,
+        concurrency: usize,
+    ) -> Vec<Result<InstallationPathResponse, NotificationRequestError>> {
+        stream::iter(installations)
+            .map(|installation| self.upsert_installation(installation))
+            .buffered(concurrency.max(1))
+            .collect()
+            .await
     }
 
     pub async fn patch_installation(
@@ -245,56 +1099,456 @@ impl NotificationHubClient {
     ) -> Result<InstallationPathResponse, NotificationRequestError> {
         let patch_json = serde_json::to_string(&patches)
             .map_err(NotificationRequestError::JsonSerializationError)?;
-        let https_host = self.host_name.replace("sb://", "https://");
         let uri = format!(
             "{}/{}/installations/{}?api-version={}",
-            &https_host, &self.hub_name, installation_id, API_VERSION
+            &self.https_host,
+            &self.hub_name,
+            urlencoding::encode(installation_id),
+            &self.api_version
         );
 
-        let mut request = Request::patch(uri);
+        let mut headers = HeaderMap::new();
+        let content_type = HeaderValue::from_static("application/json");
+        headers.insert(CONTENT_TYPE, content_type);
 
-        let sas_token = self
-            .token_provider
-            .generate_sas_token(&self.host_name)
-            .map_err(NotificationRequestError::GenerateSasTokenError)?;
-        let sas_token_header = HeaderValue::from_str(&sas_token).unwrap();
-        request = request.header(AUTHORIZATION, sas_token_header);
-
-        let content_type = HeaderValue::from_str("application/json").unwrap();
-        request = request.header(CONTENT_TYPE, content_type);
-
-        let request = request.body(Body::from(patch_json)).unwrap();
-
-        let https = HttpsConnector::new();
-        let client = Client::builder().build::<_, hyper::Body>(https);
-
-        let res = client
-            .request(request)
-            .await
-            .map_err(NotificationRequestError::HttpRequestError)?;
+        let res = self
+            .execute(Method::PATCH, &uri, headers, Bytes::from(patch_json))
+            .await?;
         if res.status() != StatusCode::OK {
-            return Err(NotificationRequestError::InvalidHttpResponse(res.status()));
-        }
-
-        let mut content_location: Option<&str> = None;
-        if res.headers().contains_key("content-location") {
-            content_location = Some(res.headers()["content-location"].to_str().unwrap());
+            return Err(error_from_response(res).await);
         }
 
-        let content_location = content_location.get_or_insert("");
-
-        Ok(InstallationPathResponse {
-            content_location: content_location.to_string(),
-        })
+        Ok(installation_path_response(&res))
     }
 
-    pub async fn send_direct_notification(
+    /// Adds and removes tags on an installation in a single patch, without
+    /// reading it first.
+    pub async fn patch_installation_tags(
         &self,
-        request_message: NotificationRequest,
-        device_token: &str,
-    ) -> Result<NotificationResponse, NotificationRequestError> {
-        self.send_notification(request_message, Some(device_token), None)
-            .await
+        installation_id: &str,
+        add: Vec<&str>,
+        remove: Vec<&str>,
+    ) -> Result<InstallationPathResponse, NotificationRequestError> {
+        let patches = add
+            .into_iter()
+            .map(InstallationPatch::add_tag)
+            .chain(remove.into_iter().map(InstallationPatch::remove_tag))
+            .collect();
+        self.patch_installation(installation_id, patches).await
+    }
+
+    /// Replaces all of an installation's tags in a single patch, without
+    /// reading it first. An empty list removes every tag.
+    pub async fn replace_tags(
+        &self,
+        installation_id: &str,
+        tags: Vec<&str>,
+    ) -> Result<InstallationPathResponse, NotificationRequestError> {
+        self.patch_installation(
+            installation_id,
+            vec![InstallationPatch::replace_tags(&tags)],
+        )
+        .await
+    }
+
+    /// Adds tags to an installation in a single patch, keeping its existing
+    /// tags and without reading it first. Fails with `NoTags`, without sending
+    /// anything, when `tags` is empty.
+    pub async fn add_tags(
+        &self,
+        installation_id: &str,
+        tags: Vec<&str>,
+    ) -> Result<InstallationPathResponse, NotificationRequestError> {
+        if tags.is_empty() {
+            return Err(NotificationRequestError::NoTags);
+        }
+        self.patch_installation_tags(installation_id, tags, Vec::new())
+            .await
+    }
+
+    /// Fetches a single page of installations. Pass the continuation token
+    /// returned by the previous page to continue the enumeration; `None` is
+    /// returned as the token once the last page has been read.
+    pub async fn list_installations_page(
+        &self,
+        continuation_token: Option<&str>,
+    ) -> Result<InstallationPage, NotificationRequestError> {
+        let mut uri = format!(
+            "{}/{}/installations?api-version={}",
+            &self.https_host, &self.hub_name, &self.api_version
+        );
+
+        if let Some(continuation_token) = continuation_token {
+            uri = format!(
+                "{}&continuationtoken={}",
+                uri,
+                urlencoding::encode(continuation_token)
+            );
+        }
+
+        let res = self
+            .execute(Method::GET, &uri, HeaderMap::new(), Bytes::new())
+            .await?;
+        if res.status() != StatusCode::OK {
+            return Err(error_from_response(res).await);
+        }
+
+        let continuation_token = res
+            .headers()
+            .get(CONTINUATION_TOKEN_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .filter(|value| !value.is_empty())
+            .map(str::to_string);
+
+        let body = hyper::body::to_bytes(res)
+            .await
+            .map_err(NotificationRequestError::HttpRequestError)?;
+        let installations: Vec<Installation> = if is_empty_body(&body) {
+            Vec::new()
+        } else {
+            serde_json::from_slice(&body).map_err(|err| invalid_response_body(err, &body))?
+        };
+
+        Ok(InstallationPage {
+            installations,
+            continuation_token,
+        })
+    }
+
+    /// Streams every installation in the hub, transparently following
+    /// continuation tokens from page to page.
+    pub fn list_installations(
+        &self,
+    ) -> impl Stream<Item = Result<Installation, NotificationRequestError>> + '_ {
+        // `None` once the last page is read, otherwise the token for the next page.
+        let first_page: Option<Option<String>> = Some(None);
+
+        stream::try_unfold(first_page, move |next_page| async move {
+            let continuation_token = match next_page {
+                Some(continuation_token) => continuation_token,
+                None => return Ok::<_, NotificationRequestError>(None),
+            };
+
+            let page = self
+                .list_installations_page(continuation_token.as_deref())
+                .await?;
+            let installations = stream::iter(page.installations.into_iter().map(Ok));
+            Ok(Some((installations, page.continuation_token.map(Some))))
+        })
+        .try_flatten()
+    }
+
+    /// Streams the installations for which `predicate` returns `true`,
+    /// paging through the hub like `list_installations`. The filter is applied
+    /// as each page arrives, since Azure cannot filter installations itself.
+    pub fn list_installations_filtered<'a>(
+        &'a self,
+        predicate: impl Fn(&Installation) -> bool + 'a,
+    ) -> impl Stream<Item = Result<Installation, NotificationRequestError>> + 'a {
+        self.list_installations()
+            .try_filter(move |installation| futures::future::ready(predicate(installation)))
+    }
+
+    pub async fn delete_installation(
+        &self,
+        installation_id: &str,
+    ) -> Result<(), NotificationRequestError> {
+        let uri = format!(
+            "{}/{}/installations/{}?api-version={}",
+            &self.https_host,
+            &self.hub_name,
+            urlencoding::encode(installation_id),
+            &self.api_version
+        );
+
+        let res = self
+            .execute(Method::DELETE, &uri, HeaderMap::new(), Bytes::new())
+            .await?;
+        match res.status() {
+            StatusCode::OK | StatusCode::NO_CONTENT => Ok(()),
+            StatusCode::NOT_FOUND => Err(NotificationRequestError::NotFound),
+            _ => Err(error_from_response(res).await),
+        }
+    }
+
+    /// Counts the registrations in the hub, in total and per platform, by
+    /// enumerating the entire registrations feed. This issues one request per
+    /// 100 registrations, so on a large hub it is slow and expensive and should
+    /// not be called routinely. Installations are not counted, and the counts
+    /// drift if registrations change while the feed is paged. Needs a `Manage`
+    /// claim.
+    pub async fn count_registrations(&self) -> Result<HubMetrics, NotificationRequestError> {
+        let mut metrics = HubMetrics::default();
+        let mut continuation_token: Option<String> = None;
+
+        loop {
+            let mut uri = format!(
+                "{}/{}/registrations?api-version={}&$top={}",
+                &self.https_host, &self.hub_name, &self.api_version, REGISTRATIONS_PAGE_SIZE
+            );
+            if let Some(continuation_token) = &continuation_token {
+                uri = format!(
+                    "{}&continuationtoken={}",
+                    uri,
+                    urlencoding::encode(continuation_token)
+                );
+            }
+
+            let res = self
+                .execute(Method::GET, &uri, HeaderMap::new(), Bytes::new())
+                .await?;
+            if res.status() != StatusCode::OK {
+                return Err(error_from_response(res).await);
+            }
+
+            continuation_token = header_string(&res, CONTINUATION_TOKEN_HEADER)
+                .filter(|continuation_token| !continuation_token.is_empty());
+
+            let body = hyper::body::to_bytes(res)
+                .await
+                .map_err(NotificationRequestError::HttpRequestError)?;
+            metrics
+                .count_feed_page(&body)
+                .map_err(|err| invalid_response_body(err, &body))?;
+
+            if continuation_token.is_none() {
+                return Ok(metrics);
+            }
+        }
+    }
+
+    /// Submits a bulk import or export job, returning it with the `job_id`
+    /// to pass to `get_notification_hub_job` while it runs.
+    pub async fn submit_notification_hub_job(
+        &self,
+        job: NotificationHubJob,
+    ) -> Result<NotificationHubJob, NotificationRequestError> {
+        let job_xml = job
+            .to_atom_entry()
+            .map_err(NotificationRequestError::XmlSerializationError)?;
+        let uri = format!(
+            "{}/{}/jobs?api-version={}",
+            &self.https_host, &self.hub_name, &self.api_version
+        );
+
+        let mut headers = HeaderMap::new();
+        let content_type = HeaderValue::from_static(ATOM_ENTRY_CONTENT_TYPE);
+        headers.insert(CONTENT_TYPE, content_type);
+
+        let res = self
+            .execute(Method::POST, &uri, headers, Bytes::from(job_xml))
+            .await?;
+        if res.status() != StatusCode::OK && res.status() != StatusCode::CREATED {
+            return Err(error_from_response(res).await);
+        }
+
+        let body = read_expected_body(res).await?;
+        NotificationHubJob::from_atom_entry(&body[..])
+            .map_err(|err| invalid_response_body(err, &body))
+    }
+
+    /// Fetches a job's current status, progress, and, once it has finished,
+    /// its output properties.
+    pub async fn get_notification_hub_job(
+        &self,
+        job_id: &str,
+    ) -> Result<NotificationHubJob, NotificationRequestError> {
+        let uri = format!(
+            "{}/{}/jobs/{}?api-version={}",
+            &self.https_host,
+            &self.hub_name,
+            urlencoding::encode(job_id),
+            &self.api_version
+        );
+
+        let res = self
+            .execute(Method::GET, &uri, HeaderMap::new(), Bytes::new())
+            .await?;
+        match res.status() {
+            StatusCode::OK => {}
+            StatusCode::NOT_FOUND => return Err(NotificationRequestError::NotFound),
+            _ => return Err(error_from_response(res).await),
+        }
+
+        let body = read_expected_body(res).await?;
+        NotificationHubJob::from_atom_entry(&body[..])
+            .map_err(|err| invalid_response_body(err, &body))
+    }
+
+    /// Lists the hub's jobs, including finished ones Azure still retains.
+    pub async fn list_notification_hub_jobs(
+        &self,
+    ) -> Result<Vec<NotificationHubJob>, NotificationRequestError> {
+        let uri = format!(
+            "{}/{}/jobs?api-version={}",
+            &self.https_host, &self.hub_name, &self.api_version
+        );
+
+        let res = self
+            .execute(Method::GET, &uri, HeaderMap::new(), Bytes::new())
+            .await?;
+        if res.status() != StatusCode::OK {
+            return Err(error_from_response(res).await);
+        }
+
+        let body = hyper::body::to_bytes(res)
+            .await
+            .map_err(NotificationRequestError::HttpRequestError)?;
+        if is_empty_body(&body) {
+            return Ok(Vec::new());
+        }
+        NotificationHubJob::from_atom_feed(&body[..])
+            .map_err(|err| invalid_response_body(err, &body))
+    }
+
+    pub async fn create_registration(
+        &self,
+        registration: RegistrationDescription,
+    ) -> Result<RegistrationDescription, NotificationRequestError> {
+        let registration_xml = registration
+            .to_atom_entry()
+            .map_err(NotificationRequestError::XmlSerializationError)?;
+        let uri = format!(
+            "{}/{}/registrations?api-version={}",
+            &self.https_host, &self.hub_name, &self.api_version
+        );
+
+        let mut headers = HeaderMap::new();
+        let content_type = HeaderValue::from_static(ATOM_ENTRY_CONTENT_TYPE);
+        headers.insert(CONTENT_TYPE, content_type);
+
+        let res = self
+            .execute(Method::POST, &uri, headers, Bytes::from(registration_xml))
+            .await?;
+        if res.status() != StatusCode::OK && res.status() != StatusCode::CREATED {
+            return Err(error_from_response(res).await);
+        }
+
+        read_registration(res).await
+    }
+
+    pub async fn get_registration(
+        &self,
+        registration_id: &str,
+    ) -> Result<RegistrationDescription, NotificationRequestError> {
+        let uri = format!(
+            "{}/{}/registrations/{}?api-version={}",
+            &self.https_host, &self.hub_name, registration_id, &self.api_version
+        );
+
+        let res = self
+            .execute(Method::GET, &uri, HeaderMap::new(), Bytes::new())
+            .await?;
+        match res.status() {
+            StatusCode::OK => read_registration(res).await,
+            StatusCode::NOT_FOUND => Err(NotificationRequestError::NotFound),
+            _ => Err(error_from_response(res).await),
+        }
+    }
+
+    /// Creates or overwrites the registration with the given ID.
+    pub async fn update_registration(
+        &self,
+        registration_id: &str,
+        registration: RegistrationDescription,
+    ) -> Result<RegistrationDescription, NotificationRequestError> {
+        let registration_xml = registration
+            .to_atom_entry()
+            .map_err(NotificationRequestError::XmlSerializationError)?;
+        let uri = format!(
+            "{}/{}/registrations/{}?api-version={}",
+            &self.https_host, &self.hub_name, registration_id, &self.api_version
+        );
+
+        let mut headers = HeaderMap::new();
+        let content_type = HeaderValue::from_static(ATOM_ENTRY_CONTENT_TYPE);
+        headers.insert(CONTENT_TYPE, content_type);
+        headers.insert(IF_MATCH, HeaderValue::from_static("*"));
+
+        let res = self
+            .execute(Method::PUT, &uri, headers, Bytes::from(registration_xml))
+            .await?;
+        if res.status() != StatusCode::OK && res.status() != StatusCode::CREATED {
+            return Err(error_from_response(res).await);
+        }
+
+        read_registration(res).await
+    }
+
+    pub async fn delete_registration(
+        &self,
+        registration_id: &str,
+    ) -> Result<(), NotificationRequestError> {
+        let uri = format!(
+            "{}/{}/registrations/{}?api-version={}",
+            &self.https_host, &self.hub_name, registration_id, &self.api_version
+        );
+
+        let mut headers = HeaderMap::new();
+        headers.insert(IF_MATCH, HeaderValue::from_static("*"));
+
+        let res = self
+            .execute(Method::DELETE, &uri, headers, Bytes::new())
+            .await?;
+        match res.status() {
+            StatusCode::OK | StatusCode::NO_CONTENT => Ok(()),
+            StatusCode::NOT_FOUND => Err(NotificationRequestError::NotFound),
+            _ => Err(error_from_response(res).await),
+        }
+    }
+
+    /// Sends a notification directly to the given device handles. A single
+    /// handle is sent in the `ServiceBusNotification-DeviceHandle` header;
+    /// several are sent together through `send_direct_batch_notification`.
+    pub async fn send_direct_notification(
+        &self,
+        request_message: NotificationRequest,
+        device_tokens: &[&str],
+    ) -> Result<NotificationResponse, NotificationRequestError> {
+        match device_tokens {
+            [] => Err(NotificationRequestError::NoDeviceHandles),
+            [device_token] => {
+                self.send_notification(request_message, Some(device_token), None)
+                    .await
+            }
+            _ => {
+                self.send_direct_batch_notification(request_message, device_tokens.to_vec())
+                    .await
+            }
+        }
+    }
+
+    /// Sends one notification directly to several device handles in a single
+    /// request, using Azure's multipart direct batch endpoint.
+    pub async fn send_direct_batch_notification(
+        &self,
+        request_message: NotificationRequest,
+        device_tokens: Vec<&str>,
+    ) -> Result<NotificationResponse, NotificationRequestError> {
+        self.check_payload_size(&request_message)?;
+
+        let uri = format!(
+            "{}/{}/messages/$batch?direct&api-version={}",
+            &self.https_host, &self.hub_name, &self.api_version
+        );
+
+        let mut headers = notification_headers(&request_message)?;
+        let content_type = HeaderValue::from_str(&format!(
+            "multipart/mixed; boundary=\"{}\"",
+            DIRECT_BATCH_BOUNDARY
+        ))
+        .map_err(|_| NotificationRequestError::InvalidHeader(CONTENT_TYPE.to_string()))?;
+        headers.insert(CONTENT_TYPE, content_type);
+
+        let body = direct_batch_body(&request_message, &device_tokens)
+            .map_err(NotificationRequestError::JsonSerializationError)?;
+
+        let res = self.execute(Method::POST, &uri, headers, body).await?;
+        if res.status() != StatusCode::CREATED {
+            return Err(error_from_response(res).await);
+        }
+
+        Ok(notification_response(&res))
     }
 
     pub async fn send_tagged_notification(
@@ -302,7 +1556,8 @@ impl NotificationHubClient {
         request_message: NotificationRequest,
         tags: Vec<&str>,
     ) -> Result<NotificationResponse, NotificationRequestError> {
-        let tag_expression = tags.join("||");
+        let tag_expression =
+            any_tag_expression(&tags).map_err(NotificationRequestError::InvalidTagExpression)?;
         self.send_notification(request_message, None, Some(&tag_expression))
             .await
     }
@@ -316,91 +1571,1321 @@ impl NotificationHubClient {
             .await
     }
 
-    async fn send_notification(
+    /// Sends a notification to every installation whose `user_id` is `user_id`,
+    /// through the `$UserId:{user_id}` tag Azure maintains for them.
+    pub async fn send_to_user(
+        &self,
+        request_message: NotificationRequest,
+        user_id: &str,
+    ) -> Result<NotificationResponse, NotificationRequestError> {
+        let tag_expression = user_id_tag(user_id);
+        self.send_notification(request_message, None, Some(&tag_expression))
+            .await
+    }
+
+    /// Sends a notification to the device behind a single registration, by
+    /// looking the registration up and sending directly to its PNS handle.
+    /// The request must be in the registration's platform format.
+    pub async fn send_to_registration(
+        &self,
+        registration_id: &str,
+        request_message: NotificationRequest,
+    ) -> Result<NotificationResponse, NotificationRequestError> {
+        let registration = self.get_registration(registration_id).await?;
+        self.send_notification(request_message, Some(registration.pns_handle()), None)
+            .await
+    }
+
+    /// Sends the same notification separately to each of the given tag
+    /// expressions, issuing at most `concurrency` requests at a time. Results
+    /// are returned in the same order as `tag_expressions`.
+    pub async fn send_tag_expression_notifications(
+        &self,
+        request_message: NotificationRequest,
+        tag_expressions: Vec<&str>,
+        concurrency: usize,
+    ) -> Vec<Result<NotificationResponse, NotificationRequestError>> {
+        stream::iter(tag_expressions)
+            .map(|tag_expression| {
+                self.send_tag_expression_notification(request_message.clone(), tag_expression)
+            })
+            .buffered(concurrency.max(1))
+            .collect()
+            .await
+    }
+
+    /// Sends a template notification, filling each device's registered template
+    /// with `properties`. Goes to the devices matching any of the given tags, or
+    /// to all devices when `tags` is empty.
+    pub async fn send_template_notification(
+        &self,
+        properties: HashMap<String, String>,
+        tags: Vec<&str>,
+    ) -> Result<NotificationResponse, NotificationRequestError> {
+        let properties_json = serde_json::to_string(&properties)
+            .map_err(NotificationRequestError::JsonSerializationError)?;
+        let request_message = NotificationRequest::new(
+            Platform::Template,
+            "application/json;charset=utf-8",
+            properties_json,
+        );
+
+        let tag_expression =
+            any_tag_expression(&tags).map_err(NotificationRequestError::InvalidTagExpression)?;
+        let tag_expression = (!tags.is_empty()).then_some(tag_expression.as_str());
+        self.send_notification(request_message, None, tag_expression)
+            .await
+    }
+
+    /// Serializes `payload` as the JSON body of a notification for `platform`
+    /// and sends it to the devices matching any of the given tags, or to all
+    /// devices when `tags` is empty.
+    pub async fn send_json_notification<T: Serialize + ?Sized>(
+        &self,
+        payload: &T,
+        platform: impl Into<Platform>,
+        tags: Vec<&str>,
+    ) -> Result<NotificationResponse, NotificationRequestError> {
+        let payload_json = serde_json::to_vec(payload)
+            .map_err(NotificationRequestError::JsonSerializationError)?;
+        let request_message =
+            NotificationRequest::new(platform, "application/json;charset=utf-8", payload_json);
+
+        let tag_expression =
+            any_tag_expression(&tags).map_err(NotificationRequestError::InvalidTagExpression)?;
+        let tag_expression = (!tags.is_empty()).then_some(tag_expression.as_str());
+        self.send_notification(request_message, None, tag_expression)
+            .await
+    }
+
+    /// Schedules a notification for delivery at `schedule_time` to the devices
+    /// matching any of the given tags, or to all devices when `tags` is empty.
+    pub async fn send_scheduled_notification(
+        &self,
+        request_message: NotificationRequest,
+        schedule_time: DateTime<Utc>,
+        tags: Vec<&str>,
+    ) -> Result<ScheduledNotificationResponse, NotificationRequestError> {
+        self.send_scheduled_notification_with_api_version(
+            request_message,
+            schedule_time,
+            tags,
+            &self.api_version,
+        )
+        .await
+    }
+
+    /// Like `send_scheduled_notification`, using `api_version` instead of the
+    /// client's API version for this call only.
+    pub async fn send_scheduled_notification_with_api_version(
+        &self,
+        request_message: NotificationRequest,
+        schedule_time: DateTime<Utc>,
+        tags: Vec<&str>,
+        api_version: &str,
+    ) -> Result<ScheduledNotificationResponse, NotificationRequestError> {
+        self.check_payload_size(&request_message)?;
+
+        let uri = format!(
+            "{}/{}/schedulednotifications?api-version={}",
+            &self.https_host, &self.hub_name, api_version
+        );
+
+        let mut headers = notification_headers(&request_message)?;
+
+        let schedule_time_header = HeaderName::from_static("servicebusnotification-scheduletime");
+        let schedule_time_value =
+            HeaderValue::from_str(&schedule_time.to_rfc3339_opts(SecondsFormat::Secs, true))
+                .map_err(|_| {
+                    NotificationRequestError::InvalidHeader(schedule_time_header.to_string())
+                })?;
+        headers.insert(schedule_time_header, schedule_time_value);
+
+        if !tags.is_empty() {
+            let tag_expression_header = HeaderName::from_static("servicebusnotification-tags");
+            let tag_expression = any_tag_expression(&tags)
+                .map_err(NotificationRequestError::InvalidTagExpression)?;
+            let tag_expression_value = HeaderValue::from_str(&tag_expression).map_err(|_| {
+                NotificationRequestError::InvalidHeader(tag_expression_header.to_string())
+            })?;
+            headers.insert(tag_expression_header, tag_expression_value);
+        }
+
+        let res = self
+            .execute(Method::POST, &uri, headers, request_message.message)
+            .await?;
+        if res.status() != StatusCode::CREATED {
+            return Err(error_from_response(res).await);
+        }
+
+        let location = header_string(&res, LOCATION.as_str()).unwrap_or_default();
+        let notification_id = notification_id_from_location(&location).unwrap_or_default();
+
+        Ok(ScheduledNotificationResponse {
+            notification_id,
+            location,
+        })
+    }
+
+    pub async fn cancel_scheduled_notification(
+        &self,
+        notification_id: &str,
+    ) -> Result<(), NotificationRequestError> {
+        self.cancel_scheduled_notification_with_api_version(notification_id, &self.api_version)
+            .await
+    }
+
+    /// Like `cancel_scheduled_notification`, using `api_version` instead of the
+    /// client's API version for this call only.
+    pub async fn cancel_scheduled_notification_with_api_version(
+        &self,
+        notification_id: &str,
+        api_version: &str,
+    ) -> Result<(), NotificationRequestError> {
+        let uri = format!(
+            "{}/{}/schedulednotifications/{}?api-version={}",
+            &self.https_host, &self.hub_name, notification_id, api_version
+        );
+
+        let res = self
+            .execute(Method::DELETE, &uri, HeaderMap::new(), Bytes::new())
+            .await?;
+        match res.status() {
+            StatusCode::OK | StatusCode::NO_CONTENT => Ok(()),
+            StatusCode::NOT_FOUND => Err(NotificationRequestError::NotFound),
+            _ => Err(error_from_response(res).await),
+        }
+    }
+
+    /// Sends a notification to a single device handle, to the devices matching a
+    /// tag expression, or, when neither is given, as a broadcast to every
+    /// registered device. Setting both returns `InvalidAudience`.
+    pub async fn send_notification(
         &self,
         request_message: NotificationRequest,
         device_token: Option<&str>,
         tag_expression: Option<&str>,
     ) -> Result<NotificationResponse, NotificationRequestError> {
-        let https_host = self.host_name.replace("sb://", "https://");
-        let mut uri = format!(
-            "{}/{}/messages?api-version={}",
-            &https_host, &self.hub_name, API_VERSION
-        );
+        self.send_notification_cancellable(request_message, device_token, tag_expression, None)
+            .await
+    }
 
-        if device_token.is_some() {
-            uri = format!("{}&direct=true", uri);
+    /// Like `send_notification`, but aborts the in-flight request and returns
+    /// `Cancelled` as soon as `cancellation_token` is cancelled. The token
+    /// takes the place of any set with `with_cancellation_token`.
+    pub async fn send_notification_with_cancellation(
+        &self,
+        request_message: NotificationRequest,
+        device_token: Option<&str>,
+        tag_expression: Option<&str>,
+        cancellation_token: &CancellationToken,
+    ) -> Result<NotificationResponse, NotificationRequestError> {
+        self.send_notification_cancellable(
+            request_message,
+            device_token,
+            tag_expression,
+            Some(cancellation_token),
+        )
+        .await
+    }
+
+    async fn send_notification_cancellable(
+        &self,
+        request_message: NotificationRequest,
+        device_token: Option<&str>,
+        tag_expression: Option<&str>,
+        cancellation_token: Option<&CancellationToken>,
+    ) -> Result<NotificationResponse, NotificationRequestError> {
+        self.check_payload_size(&request_message)?;
+
+        let (uri, headers) = self.send_target(&request_message, device_token, tag_expression)?;
+
+        let res = self
+            .execute_cancellable(
+                Method::POST,
+                &uri,
+                headers,
+                RequestBody::Buffered(request_message.message),
+                cancellation_token.or(self.cancellation_token.as_ref()),
+            )
+            .await?;
+        if res.status() != StatusCode::CREATED {
+            return Err(error_from_response(res).await);
+        }
+
+        Ok(notification_response(&res))
+    }
+
+    /// Sends a notification in test mode, to the devices matching
+    /// `tag_expression` or to all devices when it is `None`. Azure delivers it
+    /// to a small number of registrations and reports each one's outcome
+    /// directly in the response, which helps confirm that a tag expression
+    /// actually matches devices.
+    pub async fn send_test_notification(
+        &self,
+        request_message: NotificationRequest,
+        tag_expression: Option<&str>,
+    ) -> Result<TestSendOutcome, NotificationRequestError> {
+        self.check_payload_size(&request_message)?;
+
+        let (uri, headers) = self.send_target(&request_message, None, tag_expression)?;
+        let uri = format!("{}&test=true", uri);
+
+        let res = self
+            .execute(Method::POST, &uri, headers, request_message.message)
+            .await?;
+        if res.status() != StatusCode::CREATED {
+            return Err(error_from_response(res).await);
         }
 
-        let mut request = Request::post(uri);
+        let body = read_expected_body(res).await?;
+        TestSendOutcome::from_xml(&body[..]).map_err(|err| invalid_response_body(err, &body))
+    }
 
-        for (name, value) in request_message.headers.into_iter() {
-            let header_name = HeaderName::from_str(&name).unwrap();
-            let header_value = HeaderValue::from_str(&value).unwrap();
-            request = request.header(header_name, header_value);
+    /// Sends a notification whose payload is streamed to Azure from `body`
+    /// rather than held in memory, e.g. a file wrapped in
+    /// `tokio_util::io::ReaderStream`. The `message` of `request_message` is
+    /// ignored; its headers, content type and platform are used as usual.
+    ///
+    /// The send is reported, traced and cancellable like any other, but a
+    /// streamed body can only be read once: the request is sent exactly once,
+    /// even when the retry policy would retry it or Azure redirects it, and
+    /// its size is not checked against the platform limit.
+    pub async fn send_notification_stream<S, E>(
+        &self,
+        request_message: NotificationRequest,
+        body: S,
+        device_token: Option<&str>,
+        tag_expression: Option<&str>,
+    ) -> Result<NotificationResponse, NotificationRequestError>
+    where
+        S: Stream<Item = Result<Bytes, E>> + Send + 'static,
+        E: Into<Box<dyn std::error::Error + Send + Sync>> + 'static,
+    {
+        let (uri, headers) = self.send_target(&request_message, device_token, tag_expression)?;
+
+        let res = self
+            .execute_cancellable(
+                Method::POST,
+                &uri,
+                headers,
+                RequestBody::Streamed(Body::wrap_stream(body)),
+                self.cancellation_token.as_ref(),
+            )
+            .await?;
+        if res.status() != StatusCode::CREATED {
+            return Err(error_from_response(res).await);
         }
 
-        let sas_token = self
-            .token_provider
-            .generate_sas_token(&self.host_name)
-            .map_err(NotificationRequestError::GenerateSasTokenError)?;
-        let sas_token_header = HeaderValue::from_str(&sas_token).unwrap();
-        request = request.header(AUTHORIZATION, sas_token_header);
+        Ok(notification_response(&res))
+    }
 
-        let content_type = HeaderValue::from_str(&request_message.content_type).unwrap();
-        request = request.header(CONTENT_TYPE, content_type);
+    /// Builds the messages URI and headers addressing a send to either a single
+    /// device or a tag expression.
+    fn send_target(
+        &self,
+        request_message: &NotificationRequest,
+        device_token: Option<&str>,
+        tag_expression: Option<&str>,
+    ) -> Result<(String, HeaderMap), NotificationRequestError> {
+        if device_token.is_some() && tag_expression.is_some() {
+            return Err(NotificationRequestError::InvalidAudience);
+        }
 
-        let platform_header = HeaderName::from_static("servicebusnotification-format");
-        let platform_value = HeaderValue::from_str(&request_message.platform).unwrap();
-        request = request.header(platform_header, platform_value);
+        let mut uri = format!(
+            "{}/{}/messages?api-version={}",
+            &self.https_host, &self.hub_name, &self.api_version
+        );
 
         if device_token.is_some() {
+            uri = format!("{}&direct=true", uri);
+        }
+
+        let mut headers = notification_headers(request_message)?;
+
+        if let Some(device_token) = device_token {
             let device_token_header =
                 HeaderName::from_static("servicebusnotification-devicehandle");
-            let device_token_value = HeaderValue::from_str(device_token.unwrap()).unwrap();
-            request = request.header(device_token_header, device_token_value);
+            let device_token_value = HeaderValue::from_str(device_token).map_err(|_| {
+                NotificationRequestError::InvalidHeader(device_token_header.to_string())
+            })?;
+            headers.insert(device_token_header, device_token_value);
         }
 
-        if tag_expression.is_some() {
+        if let Some(tag_expression) = tag_expression {
+            validate_tag_expression(tag_expression)
+                .map_err(NotificationRequestError::InvalidTagExpression)?;
+
             let tag_expression_header = HeaderName::from_static("servicebusnotification-tags");
-            let tag_expression_value = HeaderValue::from_str(tag_expression.unwrap()).unwrap();
-            request = request.header(tag_expression_header, tag_expression_value);
+            let tag_expression_value = HeaderValue::from_str(tag_expression).map_err(|_| {
+                NotificationRequestError::InvalidHeader(tag_expression_header.to_string())
+            })?;
+            headers.insert(tag_expression_header, tag_expression_value);
         }
 
-        let request = request.body(Body::from(request_message.message)).unwrap();
+        Ok((uri, headers))
+    }
 
-        let https = HttpsConnector::new();
-        let client = Client::builder().build::<_, hyper::Body>(https);
+    /// Fetches the delivery telemetry for a sent notification, identified by the
+    /// `notification_id` returned in its `NotificationResponse`.
+    pub async fn get_notification_telemetry(
+        &self,
+        notification_id: &str,
+    ) -> Result<NotificationTelemetry, NotificationRequestError> {
+        self.get_notification_telemetry_with_api_version(notification_id, &self.api_version)
+            .await
+    }
+
+    /// Like `get_notification_telemetry`, using `api_version` instead of the
+    /// client's API version for this call only.
+    pub async fn get_notification_telemetry_with_api_version(
+        &self,
+        notification_id: &str,
+        api_version: &str,
+    ) -> Result<NotificationTelemetry, NotificationRequestError> {
+        let uri = format!(
+            "{}/{}/messages/{}?api-version={}",
+            &self.https_host, &self.hub_name, notification_id, api_version
+        );
+
+        let res = self
+            .execute(Method::GET, &uri, HeaderMap::new(), Bytes::new())
+            .await?;
+        match res.status() {
+            StatusCode::OK => {}
+            StatusCode::NOT_FOUND => return Err(NotificationRequestError::NotFound),
+            _ => return Err(error_from_response(res).await),
+        }
 
-        let res = client
-            .request(request)
+        let body = read_expected_body(res).await?;
+        NotificationTelemetry::from_xml(&body[..]).map_err(|err| invalid_response_body(err, &body))
+    }
+
+    /// Sends a notification to the devices matching any of the given tags, or
+    /// to all devices when `tags` is empty, then polls its telemetry every
+    /// `poll_interval` until Azure reports a final state. Fails with
+    /// `NotificationRequestError::Timeout` if that takes longer than `timeout`.
+    pub async fn send_and_await_outcome(
+        &self,
+        request_message: NotificationRequest,
+        tags: Vec<&str>,
+        poll_interval: Duration,
+        timeout: Duration,
+    ) -> Result<NotificationTelemetry, NotificationRequestError> {
+        let tag_expression =
+            any_tag_expression(&tags).map_err(NotificationRequestError::InvalidTagExpression)?;
+        let tag_expression = (!tags.is_empty()).then_some(tag_expression.as_str());
+        let response = self
+            .send_notification(request_message, None, tag_expression)
+            .await?;
+        let notification_id = response
+            .notification_id
+            .ok_or(NotificationRequestError::TelemetryUnavailable)?;
+
+        let poll = async {
+            loop {
+                tokio::time::sleep(poll_interval).await;
+                match self.get_notification_telemetry(&notification_id).await {
+                    Ok(telemetry) if telemetry.is_final() => return Ok(telemetry),
+                    // Telemetry can take a moment to appear after the send is accepted.
+                    Ok(_) | Err(NotificationRequestError::NotFound) => {}
+                    Err(err) => return Err(err),
+                }
+            }
+        };
+
+        tokio::time::timeout(timeout, poll)
             .await
-            .map_err(NotificationRequestError::HttpRequestError)?;
-        if res.status() != StatusCode::CREATED {
-            return Err(NotificationRequestError::InvalidHttpResponse(res.status()));
+            .map_err(|_| NotificationRequestError::Timeout)?
+    }
+
+    fn check_payload_size(
+        &self,
+        request_message: &NotificationRequest,
+    ) -> Result<(), NotificationRequestError> {
+        if !self.check_payload_size {
+            return Ok(());
+        }
+
+        let size = request_message.message.len();
+        match payload_size_limit(&request_message.platform) {
+            Some(limit) if size > limit => {
+                Err(NotificationRequestError::PayloadTooLarge { size, limit })
+            }
+            _ => Ok(()),
         }
+    }
+
+    /// Issues an authenticated request, reporting it to the
+    /// `on_request_complete` callback when one is registered.
+    pub(crate) async fn execute(
+        &self,
+        method: Method,
+        uri: &str,
+        headers: HeaderMap,
+        body: Bytes,
+    ) -> Result<Response<Body>, NotificationRequestError> {
+        self.execute_cancellable(
+            method,
+            uri,
+            headers,
+            RequestBody::Buffered(body),
+            self.cancellation_token.as_ref(),
+        )
+        .await
+    }
 
-        let mut tracking_id: Option<&str> = None;
-        if res.headers().contains_key("trackingid") {
-            tracking_id = Some(res.headers()["trackingid"].to_str().unwrap());
+    /// Like `execute`, abandoning the request, dropping any in-flight
+    /// connection, once the optional cancellation token is cancelled.
+    async fn execute_cancellable(
+        &self,
+        method: Method,
+        uri: &str,
+        headers: HeaderMap,
+        body: RequestBody,
+        cancellation_token: Option<&CancellationToken>,
+    ) -> Result<Response<Body>, NotificationRequestError> {
+        let callback = match &self.on_request_complete {
+            Some(callback) => callback,
+            None => {
+                return self
+                    .execute_traced(method, uri, headers, body, cancellation_token)
+                    .await
+            }
+        };
+
+        let started = Instant::now();
+        let mut metrics = RequestMetrics {
+            method: method.clone(),
+            uri: redact_signature(uri),
+            platform: headers
+                .get(PLATFORM_HEADER)
+                .and_then(|value| value.to_str().ok())
+                .map(Platform::from),
+            status: None,
+            elapsed: Duration::ZERO,
+            bytes_sent: body.len(),
+            bytes_received: None,
+        };
+
+        let result = self
+            .execute_traced(method, uri, headers, body, cancellation_token)
+            .await;
+        metrics.elapsed = started.elapsed();
+        if let Ok(res) = &result {
+            metrics.status = Some(res.status());
+            metrics.bytes_received =
+                header_string(res, CONTENT_LENGTH.as_str()).and_then(|length| length.parse().ok());
         }
+        callback(metrics);
 
-        let tracking_id = tracking_id.get_or_insert("");
+        result
+    }
 
-        let mut correlation_id: Option<&str> = None;
-        if res.headers().contains_key("x-ms-correlation-request-id") {
-            correlation_id = Some(
-                res.headers()["x-ms-correlation-request-id"]
-                    .to_str()
-                    .unwrap(),
+    /// Issues an authenticated request, recording it in a `tracing` span when
+    /// the `tracing` feature is enabled.
+    async fn execute_traced(
+        &self,
+        method: Method,
+        uri: &str,
+        headers: HeaderMap,
+        body: RequestBody,
+        cancellation_token: Option<&CancellationToken>,
+    ) -> Result<Response<Body>, NotificationRequestError> {
+        #[cfg(feature = "tracing")]
+        {
+            use tracing::Instrument;
+
+            let platform = headers
+                .get(PLATFORM_HEADER)
+                .and_then(|value| value.to_str().ok())
+                .unwrap_or_default();
+            let span = tracing::info_span!(
+                "notification_hub_request",
+                method = %method,
+                uri = %redact_signature(uri),
+                platform = %platform,
+                status = tracing::field::Empty,
+                tracking_id = tracing::field::Empty,
             );
+
+            let result = self
+                .execute_with_retries(method, uri, headers, body, cancellation_token)
+                .instrument(span.clone())
+                .await;
+            match &result {
+                Ok(res) => {
+                    span.record("status", res.status().as_u16());
+                    if let Some(tracking_id) = header_string(res, TRACKING_ID_HEADER) {
+                        span.record("tracking_id", tracking_id.as_str());
+                    }
+                }
+                Err(err) => tracing::warn!(parent: &span, error = %err, "request failed"),
+            }
+
+            result
+        }
+
+        #[cfg(not(feature = "tracing"))]
+        self.execute_with_retries(method, uri, headers, body, cancellation_token)
+            .await
+    }
+
+    /// Issues an authenticated request, retrying throttled or unavailable
+    /// responses and failed connections as the retry policy allows.
+    async fn execute_with_retries(
+        &self,
+        method: Method,
+        uri: &str,
+        headers: HeaderMap,
+        body: RequestBody,
+        cancellation_token: Option<&CancellationToken>,
+    ) -> Result<Response<Body>, NotificationRequestError> {
+        #[cfg(feature = "compression")]
+        let (headers, body) = match body {
+            RequestBody::Buffered(body) if self.gzip_requests && !body.is_empty() => {
+                let mut headers = headers;
+                headers.insert(CONTENT_ENCODING, HeaderValue::from_static("gzip"));
+                (
+                    headers,
+                    RequestBody::Buffered(crate::compression::gzip(&body)),
+                )
+            }
+            body => (headers, body),
+        };
+
+        // A streamed body is consumed by the first attempt, so it is never
+        // retried or redirected.
+        let resendable = matches!(body, RequestBody::Buffered(_));
+        let mut body = body;
+
+        let mut uri = Cow::Borrowed(uri);
+        let mut redirects = 0;
+        let mut attempt = 0;
+
+        loop {
+            let res = match unless_cancelled(
+                cancellation_token,
+                self.send_request(&method, &uri, &headers, body.take()),
+            )
+            .await?
+            {
+                Ok(res) => res,
+                // Nothing was written to a connection that was never made, so
+                // any request can be retried.
+                Err(NotificationRequestError::HttpRequestError(err)) if err.is_connect() => {
+                    match &self.retry_policy {
+                        Some(policy) if resendable && attempt + 1 < policy.max_attempts => {
+                            #[cfg(feature = "tracing")]
+                            tracing::debug!(attempt, "retrying request after a connect error");
+                            let backoff = tokio::time::sleep(policy.backoff(attempt));
+                            unless_cancelled(cancellation_token, backoff).await?;
+                            attempt += 1;
+                            continue;
+                        }
+                        _ => return Err(NotificationRequestError::HttpRequestError(err)),
+                    }
+                }
+                Err(err) => return Err(err),
+            };
+
+            if resendable && redirects < self.max_redirects {
+                if let Some(location) = redirect_target(&method, &uri, &res) {
+                    #[cfg(feature = "tracing")]
+                    tracing::debug!(status = res.status().as_u16(), "following redirect");
+                    uri = Cow::Owned(location);
+                    redirects += 1;
+                    continue;
+                }
+            }
+
+            match &self.retry_policy {
+                Some(policy)
+                    if resendable
+                        && attempt + 1 < policy.max_attempts
+                        && RetryPolicy::is_retryable(&method, res.status()) =>
+                {
+                    #[cfg(feature = "tracing")]
+                    tracing::debug!(status = res.status().as_u16(), attempt, "retrying request");
+                    let delay = tokio::time::sleep(policy.delay_for(&res, attempt));
+                    unless_cancelled(cancellation_token, delay).await?;
+                    attempt += 1;
+                }
+                _ => return Ok(res),
+            }
+        }
+    }
+
+    /// Sends a single authenticated request, applying the request timeout.
+    async fn send_request(
+        &self,
+        method: &Method,
+        uri: &str,
+        headers: &HeaderMap,
+        body: Body,
+    ) -> Result<Response<Body>, NotificationRequestError> {
+        if let Some(rate_limiter) = &self.rate_limiter {
+            rate_limiter.acquire().await;
+        }
+
+        let mut request = Request::builder().method(method).uri(uri);
+
+        for (name, value) in headers.iter() {
+            request = request.header(name, value);
+        }
+
+        let token = match &self.credential {
+            Credential::Sas(token_provider) => {
+                token_provider
+                    .generate_sas_token(&self.host_name)
+                    .map_err(NotificationRequestError::GenerateSasTokenError)?
+            }
+            Credential::Custom(token_provider) => token_provider
+                .token(&self.host_name)
+                .await
+                .map_err(NotificationRequestError::TokenProviderError)?,
+        };
+        let token_header = HeaderValue::from_str(&token)
+            .map_err(|_| NotificationRequestError::InvalidHeader(AUTHORIZATION.to_string()))?;
+        request = request.header(AUTHORIZATION, token_header);
+
+        let user_agent_header = HeaderValue::from_str(&self.user_agent)
+            .map_err(|_| NotificationRequestError::InvalidHeader(USER_AGENT.to_string()))?;
+        request = request.header(USER_AGENT, user_agent_header);
+
+        #[cfg(feature = "compression")]
+        {
+            request = request.header(ACCEPT_ENCODING, crate::compression::ACCEPT_ENCODING_VALUE);
+        }
+
+        let request = request
+            .body(body)
+            .map_err(NotificationRequestError::InvalidRequest)?;
+
+        #[cfg(feature = "debug-capture")]
+        let (request, captured_request) = match &self.debug_capture {
+            Some(_) => {
+                let (request, captured) = crate::debug_capture::capture_request(request).await?;
+                (request, Some(captured))
+            }
+            None => (request, None),
+        };
+
+        let res = match self.request_timeout {
+            Some(timeout) => tokio::time::timeout(timeout, self.http_client.request(request)).await,
+            None => Ok(self.http_client.request(request).await),
+        };
+        let res = match res {
+            Ok(Ok(res)) => Ok(res),
+            Ok(Err(err)) if !is_timeout(&err) => {
+                Err(NotificationRequestError::HttpRequestError(err))
+            }
+            Ok(Err(_)) | Err(_) => Err(NotificationRequestError::Timeout),
+        };
+
+        if let (Some(rate_limiter), Ok(res)) = (&self.rate_limiter, &res) {
+            if let Some(hint) = RateLimitHint::from_response(res) {
+                rate_limiter.observe(&hint);
+            }
+        }
+
+        #[cfg(feature = "compression")]
+        let res = match res {
+            Ok(res) => crate::compression::decompress_response(res).await,
+            Err(err) => Err(err),
+        };
+
+        #[cfg(feature = "debug-capture")]
+        let res = match (&self.debug_capture, captured_request) {
+            (Some(callback), Some(request)) => {
+                let (res, response) = match res {
+                    Ok(res) => {
+                        let (res, captured) = crate::debug_capture::capture_response(res).await?;
+                        (Ok(res), Some(captured))
+                    }
+                    Err(err) => (Err(err), None),
+                };
+                callback(&DebugExchange { request, response });
+                res
+            }
+            _ => res,
+        };
+
+        res
+    }
+}
+
+/// Configures a `NotificationHubClient` before it is created, as an
+/// alternative to chaining `with_*` calls on a constructed client.
+///
+/// The builder only creates clients from a connection string. Clients
+/// authorized by a token provider, such as those from `from_bearer_token` or
+/// `from_custom_token_provider`, are configured with the `with_*` methods.
+#[derive(Clone)]
+pub struct NotificationHubClientBuilder {
+    connection_string: String,
+    hub_name: String,
+    timeout: Option<Duration>,
+    connect_timeout: Option<Duration>,
+    retry_policy: Option<RetryPolicy>,
+    api_version: Option<String>,
+    sas_token_ttl: Option<chrono::Duration>,
+    user_agent: Option<String>,
+    http2: Option<bool>,
+    cloud: Option<AzureCloud>,
+    rate_limit: Option<u32>,
+    #[cfg(feature = "danger-accept-invalid-certs")]
+    accept_invalid_certs: bool,
+}
+
+impl NotificationHubClientBuilder {
+    pub fn new(connection_string: &str, hub_name: &str) -> Self {
+        Self {
+            connection_string: connection_string.to_string(),
+            hub_name: hub_name.to_string(),
+            timeout: None,
+            connect_timeout: None,
+            retry_policy: None,
+            api_version: None,
+            sas_token_ttl: None,
+            user_agent: None,
+            http2: None,
+            cloud: None,
+            rate_limit: None,
+            #[cfg(feature = "danger-accept-invalid-certs")]
+            accept_invalid_certs: false,
+        }
+    }
+
+    /// Bounds how long each request may take to receive a response.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Bounds how long establishing a connection to Azure may take.
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+
+    pub fn retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = Some(retry_policy);
+        self
+    }
+
+    pub fn api_version(mut self, api_version: &str) -> Self {
+        self.api_version = Some(api_version.to_string());
+        self
+    }
+
+    pub fn sas_token_ttl(mut self, ttl: chrono::Duration) -> Self {
+        self.sas_token_ttl = Some(ttl);
+        self
+    }
+
+    /// Overrides the default `User-Agent` of `azure-notificationhubs-rust/<version>`.
+    pub fn user_agent(mut self, user_agent: &str) -> Self {
+        self.user_agent = Some(user_agent.to_string());
+        self
+    }
+
+    /// Enables or disables negotiating HTTP/2; see `NotificationHubClient::with_http2`.
+    pub fn http2(mut self, enabled: bool) -> Self {
+        self.http2 = Some(enabled);
+        self
+    }
+
+    /// Limits outgoing requests per second; see `NotificationHubClient::with_rate_limit`.
+    pub fn rate_limit(mut self, requests_per_second: u32) -> Self {
+        self.rate_limit = Some(requests_per_second);
+        self
+    }
+
+    /// Checks that the connection string's endpoint belongs to the given cloud
+    /// when the client is built, catching mismatches before any request fails
+    /// to resolve.
+    pub fn cloud(mut self, cloud: AzureCloud) -> Self {
+        self.cloud = Some(cloud);
+        self
+    }
+
+    /// Disables TLS certificate verification; see
+    /// `NotificationHubClient::with_danger_accept_invalid_certs`. Never enable
+    /// this outside of tests.
+    #[cfg(feature = "danger-accept-invalid-certs")]
+    pub fn danger_accept_invalid_certs(mut self, enabled: bool) -> Self {
+        self.accept_invalid_certs = enabled;
+        self
+    }
+
+    pub fn build(self) -> Result<NotificationHubClient, FromConnectionStringError> {
+        if let Some(cloud) = &self.cloud {
+            let parts = ConnectionStringParts::parse(&self.connection_string)?;
+            if !cloud.matches_endpoint(parts.endpoint) {
+                return Err(FromConnectionStringError::EndpointNotInCloud);
+            }
+        }
+
+        let mut client =
+            NotificationHubClient::from_connection_string(&self.connection_string, &self.hub_name)?;
+
+        if let Some(timeout) = self.timeout {
+            client = client.with_request_timeout(timeout);
+        }
+        if let Some(enabled) = self.http2 {
+            client = client.with_http2(enabled);
+        }
+        if let Some(timeout) = self.connect_timeout {
+            client = client.with_connect_timeout(timeout);
+        }
+        #[cfg(feature = "danger-accept-invalid-certs")]
+        if self.accept_invalid_certs {
+            client = client.with_danger_accept_invalid_certs(true);
+        }
+        if let Some(retry_policy) = self.retry_policy {
+            client = client.with_retry_policy(retry_policy);
+        }
+        if let Some(api_version) = &self.api_version {
+            client = client.with_api_version(api_version);
+        }
+        if let Some(ttl) = self.sas_token_ttl {
+            client = client.with_sas_token_ttl(ttl);
+        }
+        if let Some(user_agent) = &self.user_agent {
+            client = client.with_user_agent(user_agent);
+        }
+        if let Some(requests_per_second) = self.rate_limit {
+            client = client.with_rate_limit(requests_per_second);
+        }
+
+        Ok(client)
+    }
+}
+
+/// The largest payload, in bytes, each platform's notification service accepts.
+/// Platforms without a documented limit, such as templates, are not checked.
+fn payload_size_limit(platform: &Platform) -> Option<usize> {
+    match platform {
+        Platform::Apple | Platform::Fcm | Platform::FcmV1 | Platform::Baidu => Some(4 * 1024),
+        Platform::Windows => Some(5 * 1024),
+        Platform::Template | Platform::Other(_) => None,
+    }
+}
+
+/// Builds the `multipart/mixed` body of a direct batch send: the notification
+/// itself, followed by the JSON array of device handles to deliver it to.
+pub(crate) fn direct_batch_body(
+    request_message: &NotificationRequest,
+    device_tokens: &[&str],
+) -> Result<Bytes, serde_json::Error> {
+    let devices = serde_json::to_vec(device_tokens)?;
+
+    let mut body = Vec::with_capacity(request_message.message.len() + devices.len() + 256);
+    body.extend_from_slice(
+        format!(
+            "--{}\r\nContent-Type: {}\r\nContent-Disposition: inline; name=notification\r\n\r\n",
+            DIRECT_BATCH_BOUNDARY, request_message.content_type
+        )
+        .as_bytes(),
+    );
+    body.extend_from_slice(&request_message.message);
+    body.extend_from_slice(
+        format!(
+            "\r\n--{}\r\nContent-Type: application/json\r\nContent-Disposition: inline; name=devices\r\n\r\n",
+            DIRECT_BATCH_BOUNDARY
+        )
+        .as_bytes(),
+    );
+    body.extend_from_slice(&devices);
+    body.extend_from_slice(format!("\r\n--{}--\r\n", DIRECT_BATCH_BOUNDARY).as_bytes());
+
+    Ok(Bytes::from(body))
+}
+
+fn installation_path_response(res: &Response<Body>) -> InstallationPathResponse {
+    let content_location = header_string(res, "content-location").unwrap_or_default();
+
+    InstallationPathResponse {
+        installation_id: installation_id_from_location(&content_location),
+        content_location,
+        etag: header_string(res, ETAG.as_str()),
+    }
+}
+
+/// Reads the tracking IDs and notification location from a successful send.
+pub(crate) fn notification_response(res: &Response<Body>) -> NotificationResponse {
+    let location = header_string(res, LOCATION.as_str());
+    let notification_id = location.as_deref().and_then(notification_id_from_location);
+
+    NotificationResponse {
+        tracking_id: header_string(res, TRACKING_ID_HEADER),
+        correlation_id: header_string(res, CORRELATION_ID_HEADER),
+        location,
+        notification_id,
+        rate_limit: RateLimitHint::from_response(res),
+        headers: res.headers().clone(),
+    }
+}
+
+/// The headers shared by every notification send: the caller's own headers,
+/// the content type, and the notification format.
+fn notification_headers(
+    request_message: &NotificationRequest,
+) -> Result<HeaderMap, NotificationRequestError> {
+    let mut headers = HeaderMap::new();
+
+    for (name, value) in request_message.headers.iter() {
+        let header_name = HeaderName::from_str(name)
+            .map_err(|_| NotificationRequestError::InvalidHeader(name.clone()))?;
+        let header_value = HeaderValue::from_str(value)
+            .map_err(|_| NotificationRequestError::InvalidHeader(name.clone()))?;
+        headers.insert(header_name, header_value);
+    }
+
+    let content_type = HeaderValue::from_str(&request_message.content_type)
+        .map_err(|_| NotificationRequestError::InvalidHeader(CONTENT_TYPE.to_string()))?;
+    headers.insert(CONTENT_TYPE, content_type);
+
+    let platform_header = HeaderName::from_static(PLATFORM_HEADER);
+    let platform_value = HeaderValue::from_str(request_message.platform.as_str())
+        .map_err(|_| NotificationRequestError::InvalidHeader(platform_header.to_string()))?;
+    headers.insert(platform_header, platform_value);
+
+    Ok(headers)
+}
+
+#[cfg(not(any(feature = "native-tls", feature = "rustls")))]
+compile_error!("either the `native-tls` or the `rustls` feature must be enabled");
+
+type HttpsClient = Client<BoxedConnector, Body>;
+
+fn build_http_connector(connect_timeout: Option<Duration>) -> HttpConnector {
+    let mut http = HttpConnector::new();
+    http.enforce_http(false);
+    http.set_connect_timeout(connect_timeout);
+    http
+}
+
+/// Builds the HTTPS client using the TLS backend selected by the crate features,
+/// preferring `rustls` when both are enabled.
+#[cfg(feature = "rustls")]
+#[cfg_attr(not(feature = "danger-accept-invalid-certs"), allow(unused_variables))]
+fn build_https_client(
+    connect_timeout: Option<Duration>,
+    http2: bool,
+    accept_invalid_certs: bool,
+) -> HttpsClient {
+    let builder = hyper_rustls::HttpsConnectorBuilder::new();
+    #[cfg(feature = "danger-accept-invalid-certs")]
+    let builder = if accept_invalid_certs {
+        builder.with_tls_config(crate::danger::rustls_config())
+    } else {
+        builder.with_native_roots()
+    };
+    #[cfg(not(feature = "danger-accept-invalid-certs"))]
+    let builder = builder.with_native_roots();
+    let builder = builder.https_or_http().enable_http1();
+    let https = if http2 {
+        builder
+            .enable_http2()
+            .wrap_connector(build_http_connector(connect_timeout))
+    } else {
+        builder.wrap_connector(build_http_connector(connect_timeout))
+    };
+    Client::builder().build::<_, Body>(BoxedConnector::new(https))
+}
+
+/// `hyper-tls` does not support ALPN, so connections always use HTTP/1.1.
+#[cfg(all(feature = "native-tls", not(feature = "rustls")))]
+#[cfg_attr(not(feature = "danger-accept-invalid-certs"), allow(unused_variables))]
+fn build_https_client(
+    connect_timeout: Option<Duration>,
+    _http2: bool,
+    accept_invalid_certs: bool,
+) -> HttpsClient {
+    #[cfg(feature = "danger-accept-invalid-certs")]
+    if accept_invalid_certs {
+        let https = hyper_tls::HttpsConnector::from((
+            build_http_connector(connect_timeout),
+            crate::danger::native_tls_connector(),
+        ));
+        return Client::builder().build::<_, Body>(BoxedConnector::new(https));
+    }
+
+    let https =
+        hyper_tls::HttpsConnector::new_with_connector(build_http_connector(connect_timeout));
+    Client::builder().build::<_, Body>(BoxedConnector::new(https))
+}
+
+/// Whether a hyper error was caused by a timed out I/O operation, such as
+/// the connector's connect timeout elapsing.
+fn is_timeout(err: &hyper::Error) -> bool {
+    let mut source = std::error::Error::source(err);
+    while let Some(err) = source {
+        if let Some(io_err) = err.downcast_ref::<std::io::Error>() {
+            if io_err.kind() == std::io::ErrorKind::TimedOut {
+                return true;
+            }
         }
+        source = err.source();
+    }
+
+    false
+}
+
+async fn read_registration(
+    res: Response<Body>,
+) -> Result<RegistrationDescription, NotificationRequestError> {
+    let body = read_expected_body(res).await?;
+    RegistrationDescription::from_atom_entry(&body[..])
+        .map_err(|err| invalid_response_body(err, &body))
+}
+
+/// How much of an undeserializable response body is kept for diagnostics
+const RESPONSE_BODY_SNIPPET_LEN: usize = 1024;
+
+/// Reads a response body that is expected to hold a document, so that an
+/// empty one is reported as such instead of as a parse failure.
+pub(crate) async fn read_expected_body(
+    res: Response<Body>,
+) -> Result<Bytes, NotificationRequestError> {
+    let body = hyper::body::to_bytes(res)
+        .await
+        .map_err(NotificationRequestError::HttpRequestError)?;
+    if is_empty_body(&body) {
+        return Err(NotificationRequestError::EmptyResponse);
+    }
+
+    Ok(body)
+}
+
+fn is_empty_body(body: &[u8]) -> bool {
+    body.iter().all(u8::is_ascii_whitespace)
+}
+
+/// Builds an `InvalidResponseBody` error carrying the start of the body that
+/// failed to deserialize, so the caller can see what Azure actually returned.
+pub(crate) fn invalid_response_body(
+    err: impl std::fmt::Display,
+    body: &[u8],
+) -> NotificationRequestError {
+    let body = String::from_utf8_lossy(body);
+    let mut end = body.len().min(RESPONSE_BODY_SNIPPET_LEN);
+    while !body.is_char_boundary(end) {
+        end -= 1;
+    }
+
+    NotificationRequestError::InvalidResponseBody {
+        message: err.to_string(),
+        body: body[..end].to_string(),
+    }
+}
 
-        let correlation_id = correlation_id.get_or_insert("");
+/// Builds an `Unauthorized` or `InvalidHttpResponse` error, keeping the response
+/// body since Azure usually explains the failure there.
+pub(crate) async fn error_from_response(res: Response<Body>) -> NotificationRequestError {
+    let status = res.status();
+    let location = header_string(&res, LOCATION.as_str());
+    let tracking_id = header_string(&res, TRACKING_ID_HEADER);
+    let correlation_id = header_string(&res, CORRELATION_ID_HEADER);
+    let body = match hyper::body::to_bytes(res.into_body()).await {
+        Ok(bytes) => String::from_utf8_lossy(&bytes).into_owned(),
+        Err(_) => String::new(),
+    };
+
+    match status {
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => {
+            NotificationRequestError::Unauthorized { status, body }
+        }
+        _ if status.is_redirection() => NotificationRequestError::Redirect { status, location },
+        _ => NotificationRequestError::InvalidHttpResponse {
+            status,
+            azure_error: AzureError::parse(&body),
+            body,
+            tracking_id,
+            correlation_id,
+        },
+    }
+}
+
+/// The body of a request: buffered, so it can be compressed and sent again on
+/// a retry or redirect, or streamed, so it can be sent only once.
+enum RequestBody {
+    Buffered(Bytes),
+    Streamed(Body),
+}
+
+impl RequestBody {
+    /// The size reported in `RequestMetrics`, which is zero for a streamed
+    /// body since it is not known up front.
+    fn len(&self) -> u64 {
+        match self {
+            RequestBody::Buffered(body) => body.len() as u64,
+            RequestBody::Streamed(_) => 0,
+        }
+    }
+
+    /// The body for the next attempt. A streamed body is handed over whole,
+    /// leaving an empty one behind.
+    fn take(&mut self) -> Body {
+        match self {
+            RequestBody::Buffered(body) => Body::from(body.clone()),
+            RequestBody::Streamed(body) => std::mem::take(body),
+        }
+    }
+}
+
+/// Runs `future` to completion, or fails with `Cancelled` as soon as the
+/// optional cancellation token is cancelled, dropping the future.
+async fn unless_cancelled<F: Future>(
+    cancellation_token: Option<&CancellationToken>,
+    future: F,
+) -> Result<F::Output, NotificationRequestError> {
+    match cancellation_token {
+        Some(cancellation_token) => tokio::select! {
+            biased;
+            _ = cancellation_token.cancelled() => Err(NotificationRequestError::Cancelled),
+            output = future => Ok(output),
+        },
+        None => Ok(future.await),
+    }
+}
 
-        Ok(NotificationResponse {
-            tracking_id: tracking_id.to_string(),
-            correlation_id: correlation_id.to_string(),
+/// The absolute URI a redirect response points to, when it is safe to follow:
+/// to the same host and port over `https`, and for methods other than `GET`
+/// and `HEAD` only with a status that keeps the method and body.
+fn redirect_target(method: &Method, uri: &str, res: &Response<Body>) -> Option<String> {
+    let keeps_method = match res.status() {
+        StatusCode::TEMPORARY_REDIRECT | StatusCode::PERMANENT_REDIRECT => true,
+        StatusCode::MOVED_PERMANENTLY | StatusCode::FOUND | StatusCode::SEE_OTHER => false,
+        _ => return None,
+    };
+    if !keeps_method && method != Method::GET && method != Method::HEAD {
+        return None;
+    }
+
+    let base = url::Url::parse(uri).ok()?;
+    let location = res.headers().get(LOCATION)?.to_str().ok()?;
+    let target = base.join(location).ok()?;
+    let same_origin = target.scheme() == "https"
+        && target.host_str() == base.host_str()
+        && target.port_or_known_default() == base.port_or_known_default();
+
+    same_origin.then(|| target.to_string())
+}
+
+/// Masks the value of any `sig` query parameter so SAS signatures never reach logs.
+pub(crate) fn redact_signature(uri: &str) -> String {
+    let (path, query) = match uri.split_once('?') {
+        Some(parts) => parts,
+        None => return uri.to_string(),
+    };
+
+    let query = query
+        .split('&')
+        .map(|pair| {
+            if pair.starts_with("sig=") {
+                "sig=REDACTED"
+            } else {
+                pair
+            }
         })
+        .collect::<Vec<_>>()
+        .join("&");
+
+    format!("{}?{}", path, query)
+}
+
+/// Extracts the trailing ID from a resource location such as
+/// `https://{namespace}/{hub}/messages/{id}?api-version=2017-04`.
+pub(crate) fn notification_id_from_location(location: &str) -> Option<String> {
+    let path = location.split('?').next()?;
+    path.rsplit('/')
+        .find(|segment| !segment.is_empty())
+        .map(str::to_string)
+}
+
+/// The tag Azure adds to every installation with the given `user_id`.
+pub(crate) fn user_id_tag(user_id: &str) -> String {
+    format!("$UserId:{{{}}}", user_id)
+}
+
+/// Turns a namespace endpoint such as `sb://<namespace>.servicebus.windows.net/`
+/// into the `https` base URL requests are sent to, without a trailing `/`
+/// since request paths are appended with one. The host, any explicit port
+/// and any path are kept; a missing scheme is assumed.
+pub(crate) fn https_endpoint(endpoint: &str) -> String {
+    let parsed = url::Url::parse(endpoint)
+        .ok()
+        .filter(|url| url.has_host())
+        .or_else(|| url::Url::parse(&format!("https://{}", endpoint)).ok());
+    let url = match parsed.as_ref().and_then(|url| Some((url, url.host_str()?))) {
+        Some((url, host)) => {
+            let port = match url.port().filter(|port| *port != 443) {
+                Some(port) => format!(":{}", port),
+                None => String::new(),
+            };
+            format!("https://{}{}{}", host, port, url.path())
+        }
+        None => endpoint.replace("sb://", "https://"),
+    };
+
+    url.trim_end_matches('/').to_string()
+}
+
+/// Extracts the percent-decoded installation ID from a content location such
+/// as `https://{namespace}/{hub}/installations/{id}?api-version=2017-04`.
+pub(crate) fn installation_id_from_location(location: &str) -> Option<String> {
+    let path = location.split('?').next()?;
+    let (_, id) = path.rsplit_once("/installations/")?;
+    let id = id.trim_end_matches('/');
+    if id.is_empty() {
+        return None;
+    }
+
+    urlencoding::decode(id).ok().map(|id| id.into_owned())
+}
+
+fn header_string(res: &Response<Body>, name: &str) -> Option<String> {
+    res.headers()
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string)
+}
+
+/// Installation timestamps are ISO 8601, sometimes with seven fractional digits
+/// and no offset, in which case they are UTC. Missing, empty, or unparseable
+/// values deserialize as `None`.
+mod azure_date_time {
+    use chrono::{DateTime, NaiveDateTime, SecondsFormat, Utc};
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(
+        date: &Option<DateTime<Utc>>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match date {
+            Some(date) => {
+                serializer.serialize_str(&date.to_rfc3339_opts(SecondsFormat::AutoSi, true))
+            }
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<DateTime<Utc>>, D::Error> {
+        Ok(Option::<String>::deserialize(deserializer)?.and_then(|date| parse(&date)))
+    }
+
+    fn parse(date: &str) -> Option<DateTime<Utc>> {
+        DateTime::parse_from_rfc3339(date)
+            .map(|date| date.with_timezone(&Utc))
+            .or_else(|_| {
+                NaiveDateTime::parse_from_str(date, "%Y-%m-%dT%H:%M:%S%.f")
+                    .map(|date| date.and_utc())
+            })
+            .ok()
     }
 }
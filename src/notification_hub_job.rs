@@ -0,0 +1,203 @@
+use crate::registration::{ATOM_NAMESPACE, SERVICE_BUS_NAMESPACE};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// What a bulk job does. Import jobs read one record per line from
+/// `import_file_uri`; export jobs write to `output_container_uri`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum JobType {
+    ExportRegistrations,
+    ImportCreateRegistrations,
+    ImportUpdateRegistrations,
+    ImportDeleteRegistrations,
+    ImportUpsertRegistrations,
+    ExportInstallations,
+    ImportUpsertInstallations,
+    ImportDeleteInstallations,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+pub enum JobStatus {
+    Started,
+    Running,
+    Completed,
+    Failed,
+    /// A status this crate does not know about yet
+    #[serde(other)]
+    Unknown,
+}
+
+/// A bulk import or export job, which Azure runs in the background against
+/// files in blob storage.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct NotificationHubJob {
+    #[serde(default, skip_serializing)]
+    pub job_id: Option<String>,
+    #[serde(rename = "Type")]
+    pub job_type: JobType,
+    #[serde(default, skip_serializing)]
+    pub status: Option<JobStatus>,
+    /// How far the job has got, from `0` to `100`
+    #[serde(default, skip_serializing)]
+    pub progress: Option<f64>,
+    /// A SAS URL to a blob container the job writes its output and failures to
+    pub output_container_uri: String,
+    /// A SAS URL to the blob an import job reads from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub import_file_uri: Option<String>,
+    #[serde(default, skip_serializing)]
+    pub failure: Option<String>,
+    /// The results of a finished job, such as `OutputFilePath` and `FailedFilePath`
+    #[serde(
+        default,
+        skip_serializing,
+        deserialize_with = "output_properties::deserialize"
+    )]
+    pub output_properties: HashMap<String, String>,
+    #[serde(default, skip_serializing)]
+    pub created_at: Option<String>,
+    #[serde(default, skip_serializing)]
+    pub updated_at: Option<String>,
+}
+
+impl NotificationHubJob {
+    /// An export job writing to `output_container_uri`.
+    pub fn export(job_type: JobType, output_container_uri: impl Into<String>) -> Self {
+        Self {
+            job_id: None,
+            job_type,
+            status: None,
+            progress: None,
+            output_container_uri: output_container_uri.into(),
+            import_file_uri: None,
+            failure: None,
+            output_properties: HashMap::new(),
+            created_at: None,
+            updated_at: None,
+        }
+    }
+
+    /// An import job reading `import_file_uri` and reporting to `output_container_uri`.
+    pub fn import(
+        job_type: JobType,
+        import_file_uri: impl Into<String>,
+        output_container_uri: impl Into<String>,
+    ) -> Self {
+        Self {
+            import_file_uri: Some(import_file_uri.into()),
+            ..Self::export(job_type, output_container_uri)
+        }
+    }
+
+    /// Whether the job has finished, successfully or not.
+    pub fn is_finished(&self) -> bool {
+        matches!(self.status, Some(JobStatus::Completed | JobStatus::Failed))
+    }
+
+    /// Wraps the job in the Atom entry envelope the jobs API expects.
+    pub(crate) fn to_atom_entry(&self) -> Result<String, quick_xml::DeError> {
+        let entry = EntryOut {
+            xmlns: ATOM_NAMESPACE,
+            content: ContentOut {
+                content_type: "application/xml",
+                job: JobOut {
+                    xmlns: SERVICE_BUS_NAMESPACE,
+                    job: self,
+                },
+            },
+        };
+
+        quick_xml::se::to_string(&entry)
+    }
+
+    pub(crate) fn from_atom_entry<R: std::io::BufRead>(
+        reader: R,
+    ) -> Result<Self, quick_xml::DeError> {
+        let entry: EntryIn = quick_xml::de::from_reader(reader)?;
+        Ok(entry.content.job)
+    }
+
+    pub(crate) fn from_atom_feed<R: std::io::BufRead>(
+        reader: R,
+    ) -> Result<Vec<Self>, quick_xml::DeError> {
+        let feed: FeedIn = quick_xml::de::from_reader(reader)?;
+        Ok(feed
+            .entries
+            .into_iter()
+            .map(|entry| entry.content.job)
+            .collect())
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename = "entry")]
+struct EntryOut<'a> {
+    #[serde(rename = "@xmlns")]
+    xmlns: &'static str,
+    content: ContentOut<'a>,
+}
+
+#[derive(Serialize)]
+struct ContentOut<'a> {
+    #[serde(rename = "@type")]
+    content_type: &'static str,
+    #[serde(rename = "NotificationHubJob")]
+    job: JobOut<'a>,
+}
+
+#[derive(Serialize)]
+struct JobOut<'a> {
+    #[serde(rename = "@xmlns")]
+    xmlns: &'static str,
+    #[serde(flatten)]
+    job: &'a NotificationHubJob,
+}
+
+#[derive(Deserialize)]
+struct FeedIn {
+    #[serde(rename = "entry", default)]
+    entries: Vec<EntryIn>,
+}
+
+#[derive(Deserialize)]
+struct EntryIn {
+    content: ContentIn,
+}
+
+#[derive(Deserialize)]
+struct ContentIn {
+    #[serde(rename = "NotificationHubJob")]
+    job: NotificationHubJob,
+}
+
+/// Output properties are a serialized .NET dictionary:
+/// `<OutputProperties><KeyValueOfstringstring><Key/><Value/></KeyValueOfstringstring></OutputProperties>`.
+mod output_properties {
+    use serde::{Deserialize, Deserializer};
+    use std::collections::HashMap;
+
+    #[derive(Deserialize)]
+    struct OutputProperties {
+        #[serde(rename = "KeyValueOfstringstring", default)]
+        entries: Vec<KeyValue>,
+    }
+
+    #[derive(Deserialize)]
+    #[serde(rename_all = "PascalCase")]
+    struct KeyValue {
+        key: String,
+        #[serde(default)]
+        value: String,
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<HashMap<String, String>, D::Error> {
+        Ok(OutputProperties::deserialize(deserializer)?
+            .entries
+            .into_iter()
+            .map(|entry| (entry.key, entry.value))
+            .collect())
+    }
+}
@@ -0,0 +1,285 @@
+use crate::platform::Platform;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::fmt;
+use std::str::FromStr;
+
+/// Per-message delivery details reported by Azure once a notification has
+/// been processed, as returned by `get_notification_telemetry`.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct NotificationTelemetry {
+    pub notification_id: String,
+    #[serde(default)]
+    pub location: Option<String>,
+    /// One of `Enqueued`, `Processing`, `Completed`, `Abandoned`,
+    /// `NoTargetFound`, `Cancelled`, `DetailedStateAvailable`, or `Unknown`
+    pub state: String,
+    #[serde(default)]
+    pub enqueue_time: Option<String>,
+    #[serde(default)]
+    pub start_time: Option<String>,
+    #[serde(default)]
+    pub end_time: Option<String>,
+    #[serde(default)]
+    pub target_platforms: Option<String>,
+    #[serde(default, deserialize_with = "outcome_counts::deserialize")]
+    pub apns_outcome_counts: Vec<OutcomeCount>,
+    #[serde(default, deserialize_with = "outcome_counts::deserialize")]
+    pub wns_outcome_counts: Vec<OutcomeCount>,
+    #[serde(default, deserialize_with = "outcome_counts::deserialize")]
+    pub mpns_outcome_counts: Vec<OutcomeCount>,
+    #[serde(
+        default,
+        rename = "GcmOutcomeCounts",
+        deserialize_with = "outcome_counts::deserialize"
+    )]
+    pub fcm_outcome_counts: Vec<OutcomeCount>,
+    #[serde(
+        default,
+        rename = "FcmV1OutcomeCounts",
+        deserialize_with = "outcome_counts::deserialize"
+    )]
+    pub fcm_v1_outcome_counts: Vec<OutcomeCount>,
+    #[serde(default, deserialize_with = "outcome_counts::deserialize")]
+    pub adm_outcome_counts: Vec<OutcomeCount>,
+    #[serde(default, deserialize_with = "outcome_counts::deserialize")]
+    pub baidu_outcome_counts: Vec<OutcomeCount>,
+    /// A SAS URL to a blob listing the individual PNS errors, when available
+    #[serde(default)]
+    pub pns_error_details_uri: Option<String>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct OutcomeCount {
+    pub name: String,
+    pub count: u64,
+}
+
+impl OutcomeCount {
+    pub fn outcome(&self) -> NotificationOutcome {
+        let Ok(outcome) = self.name.parse();
+        outcome
+    }
+}
+
+/// What a PNS reported for the notifications it was handed, as named in the
+/// outcome counts of `NotificationTelemetry`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum NotificationOutcome {
+    Success,
+    Abandoned,
+    BadChannel,
+    ChannelDisconnected,
+    ChannelThrottled,
+    Dropped,
+    ExpiredChannel,
+    InvalidCredentials,
+    InvalidNotificationFormat,
+    InvalidNotificationSize,
+    InvalidToken,
+    NoTargets,
+    PnsInterfaceError,
+    PnsInvalidNotificationFormat,
+    PnsServerError,
+    PnsUnavailable,
+    PnsUnreachable,
+    Skipped,
+    Throttled,
+    UnknownError,
+    WrongToken,
+    /// An outcome this crate does not know about yet, kept verbatim.
+    Other(String),
+}
+
+impl NotificationOutcome {
+    pub fn as_str(&self) -> &str {
+        match self {
+            NotificationOutcome::Success => "Success",
+            NotificationOutcome::Abandoned => "Abandoned",
+            NotificationOutcome::BadChannel => "BadChannel",
+            NotificationOutcome::ChannelDisconnected => "ChannelDisconnected",
+            NotificationOutcome::ChannelThrottled => "ChannelThrottled",
+            NotificationOutcome::Dropped => "Dropped",
+            NotificationOutcome::ExpiredChannel => "ExpiredChannel",
+            NotificationOutcome::InvalidCredentials => "InvalidCredentials",
+            NotificationOutcome::InvalidNotificationFormat => "InvalidNotificationFormat",
+            NotificationOutcome::InvalidNotificationSize => "InvalidNotificationSize",
+            NotificationOutcome::InvalidToken => "InvalidToken",
+            NotificationOutcome::NoTargets => "NoTargets",
+            NotificationOutcome::PnsInterfaceError => "PnsInterfaceError",
+            NotificationOutcome::PnsInvalidNotificationFormat => "PnsInvalidNotificationFormat",
+            NotificationOutcome::PnsServerError => "PnsServerError",
+            NotificationOutcome::PnsUnavailable => "PnsUnavailable",
+            NotificationOutcome::PnsUnreachable => "PnsUnreachable",
+            NotificationOutcome::Skipped => "Skipped",
+            NotificationOutcome::Throttled => "Throttled",
+            NotificationOutcome::UnknownError => "UnknownError",
+            NotificationOutcome::WrongToken => "WrongToken",
+            NotificationOutcome::Other(outcome) => outcome,
+        }
+    }
+
+    /// Whether the PNS accepted the notification for delivery.
+    pub fn is_success(&self) -> bool {
+        *self == NotificationOutcome::Success
+    }
+}
+
+impl fmt::Display for NotificationOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Ignores case. Unknown names parse as `NotificationOutcome::Other`.
+impl FromStr for NotificationOutcome {
+    type Err = Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s.to_ascii_lowercase().as_str() {
+            "success" => NotificationOutcome::Success,
+            "abandoned" => NotificationOutcome::Abandoned,
+            "badchannel" => NotificationOutcome::BadChannel,
+            "channeldisconnected" => NotificationOutcome::ChannelDisconnected,
+            "channelthrottled" => NotificationOutcome::ChannelThrottled,
+            "dropped" => NotificationOutcome::Dropped,
+            "expiredchannel" => NotificationOutcome::ExpiredChannel,
+            "invalidcredentials" => NotificationOutcome::InvalidCredentials,
+            "invalidnotificationformat" => NotificationOutcome::InvalidNotificationFormat,
+            "invalidnotificationsize" => NotificationOutcome::InvalidNotificationSize,
+            "invalidtoken" => NotificationOutcome::InvalidToken,
+            "notargets" => NotificationOutcome::NoTargets,
+            "pnsinterfaceerror" => NotificationOutcome::PnsInterfaceError,
+            "pnsinvalidnotificationformat" => NotificationOutcome::PnsInvalidNotificationFormat,
+            "pnsservererror" => NotificationOutcome::PnsServerError,
+            "pnsunavailable" => NotificationOutcome::PnsUnavailable,
+            "pnsunreachable" => NotificationOutcome::PnsUnreachable,
+            "skipped" => NotificationOutcome::Skipped,
+            "throttled" => NotificationOutcome::Throttled,
+            "unknownerror" => NotificationOutcome::UnknownError,
+            "wrongtoken" => NotificationOutcome::WrongToken,
+            _ => NotificationOutcome::Other(s.to_string()),
+        })
+    }
+}
+
+impl NotificationTelemetry {
+    /// Whether Azure has finished processing the notification, so its state
+    /// and outcome counts will not change any more.
+    pub fn is_final(&self) -> bool {
+        !matches!(self.state.as_str(), "Enqueued" | "Processing" | "Unknown")
+    }
+
+    /// The outcome counts of every platform the notification was sent to,
+    /// keyed by platform and then by outcome. Platforms without counts are
+    /// left out; MPNS and ADM appear as `Platform::Other("mpns")` and
+    /// `Platform::Other("adm")`.
+    pub fn outcomes(&self) -> HashMap<Platform, HashMap<NotificationOutcome, u64>> {
+        [
+            (Platform::Apple, &self.apns_outcome_counts),
+            (Platform::Windows, &self.wns_outcome_counts),
+            (
+                Platform::Other("mpns".to_string()),
+                &self.mpns_outcome_counts,
+            ),
+            (Platform::Fcm, &self.fcm_outcome_counts),
+            (Platform::FcmV1, &self.fcm_v1_outcome_counts),
+            (Platform::Other("adm".to_string()), &self.adm_outcome_counts),
+            (Platform::Baidu, &self.baidu_outcome_counts),
+        ]
+        .into_iter()
+        .filter(|(_, counts)| !counts.is_empty())
+        .map(|(platform, counts)| {
+            let mut outcomes = HashMap::new();
+            for count in counts {
+                *outcomes.entry(count.outcome()).or_insert(0) += count.count;
+            }
+            (platform, outcomes)
+        })
+        .collect()
+    }
+
+    /// How many notifications ended with `outcome`, across all platforms.
+    pub fn total(&self, outcome: &NotificationOutcome) -> u64 {
+        self.outcomes()
+            .values()
+            .filter_map(|outcomes| outcomes.get(outcome))
+            .sum()
+    }
+
+    pub(crate) fn from_xml<R: std::io::BufRead>(reader: R) -> Result<Self, quick_xml::DeError> {
+        quick_xml::de::from_reader(reader)
+    }
+}
+
+/// The per-registration results Azure returns synchronously for a test send.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct TestSendOutcome {
+    #[serde(default)]
+    pub success: u64,
+    #[serde(default)]
+    pub failure: u64,
+    #[serde(default, deserialize_with = "registration_results::deserialize")]
+    pub results: Vec<RegistrationResult>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct RegistrationResult {
+    #[serde(default)]
+    pub application_platform: String,
+    #[serde(default)]
+    pub pns_handle: String,
+    #[serde(default)]
+    pub registration_id: String,
+    /// A description of what the PNS reported, e.g. that the notification was
+    /// sent or that the handle is no longer valid
+    #[serde(default)]
+    pub outcome: String,
+}
+
+impl TestSendOutcome {
+    pub(crate) fn from_xml<R: std::io::BufRead>(reader: R) -> Result<Self, quick_xml::DeError> {
+        quick_xml::de::from_reader(reader)
+    }
+}
+
+/// Outcome counts are nested as `<ApnsOutcomeCounts><Outcome>...</Outcome></ApnsOutcomeCounts>`.
+mod outcome_counts {
+    use super::OutcomeCount;
+    use serde::{Deserialize, Deserializer};
+
+    #[derive(Deserialize)]
+    struct OutcomeCounts {
+        #[serde(rename = "Outcome", default)]
+        outcomes: Vec<OutcomeCount>,
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<OutcomeCount>, D::Error> {
+        Ok(OutcomeCounts::deserialize(deserializer)?.outcomes)
+    }
+}
+
+/// Results are nested as `<Results><RegistrationResult>...</RegistrationResult></Results>`.
+mod registration_results {
+    use super::RegistrationResult;
+    use serde::{Deserialize, Deserializer};
+
+    #[derive(Deserialize)]
+    struct RegistrationResults {
+        #[serde(rename = "RegistrationResult", default)]
+        results: Vec<RegistrationResult>,
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<RegistrationResult>, D::Error> {
+        Ok(RegistrationResults::deserialize(deserializer)?.results)
+    }
+}
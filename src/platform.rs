@@ -0,0 +1,138 @@
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::convert::Infallible;
+use std::fmt;
+use std::str::FromStr;
+
+/// A push notification service that Azure can deliver to.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Platform {
+    Apple,
+    /// Legacy FCM, which Azure still names `gcm`.
+    Fcm,
+    FcmV1,
+    Windows,
+    Baidu,
+    Template,
+    /// A platform this crate does not know about yet, kept verbatim.
+    Other(String),
+}
+
+impl Platform {
+    /// The name used in the `ServiceBusNotification-Format` header of a send.
+    pub fn as_str(&self) -> &str {
+        match self {
+            Platform::Apple => "apple",
+            Platform::Fcm => "gcm",
+            Platform::FcmV1 => "fcmv1",
+            Platform::Windows => "windows",
+            Platform::Baidu => "baidu",
+            Platform::Template => "template",
+            Platform::Other(platform) => platform,
+        }
+    }
+
+    /// The name used in the `platform` field of an installation, which differs
+    /// from the send format for Apple and Windows.
+    pub fn as_installation_str(&self) -> &str {
+        match self {
+            Platform::Apple => "apns",
+            Platform::Windows => "wns",
+            platform => platform.as_str(),
+        }
+    }
+
+    /// The content type the platform's payloads are sent with unless a builder
+    /// is told otherwise, or `None` for platforms this crate does not know.
+    /// Windows raw notifications use `application/octet-stream` instead.
+    pub fn default_content_type(&self) -> Option<&'static str> {
+        match self {
+            Platform::Apple | Platform::Fcm | Platform::FcmV1 | Platform::Template => {
+                Some("application/json;charset=utf-8")
+            }
+            Platform::Windows => Some("application/xml"),
+            Platform::Baidu => Some("application/x-www-form-urlencoded;charset=utf-8"),
+            Platform::Other(_) => None,
+        }
+    }
+}
+
+impl fmt::Display for Platform {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Accepts both the send format and installation names, ignoring case.
+/// Unknown names parse as `Platform::Other`.
+impl FromStr for Platform {
+    type Err = Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s.to_ascii_lowercase().as_str() {
+            "apple" | "apns" => Platform::Apple,
+            "gcm" | "fcm" => Platform::Fcm,
+            "fcmv1" => Platform::FcmV1,
+            "windows" | "wns" => Platform::Windows,
+            "baidu" => Platform::Baidu,
+            "template" => Platform::Template,
+            _ => Platform::Other(s.to_string()),
+        })
+    }
+}
+
+impl From<&str> for Platform {
+    fn from(platform: &str) -> Self {
+        let Ok(platform) = platform.parse();
+        platform
+    }
+}
+
+impl From<String> for Platform {
+    fn from(platform: String) -> Self {
+        platform.as_str().into()
+    }
+}
+
+impl Default for Platform {
+    fn default() -> Self {
+        Platform::Other(String::new())
+    }
+}
+
+impl PartialEq<str> for Platform {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl PartialEq<&str> for Platform {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+impl Serialize for Platform {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for Platform {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(String::deserialize(deserializer)?.into())
+    }
+}
+
+/// Serializes a platform with its installation name rather than its send format.
+pub(crate) mod installation {
+    use super::Platform;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(platform: &Platform, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(platform.as_installation_str())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Platform, D::Error> {
+        Platform::deserialize(deserializer)
+    }
+}
@@ -0,0 +1,28 @@
+/// How urgently a notification should be delivered, mapped by each builder to
+/// its platform's own representation.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Priority {
+    /// Delivered immediately, waking the device if needed
+    #[default]
+    High,
+    /// Delivered at a time that conserves the device's battery
+    Normal,
+}
+
+impl Priority {
+    /// The `apns-priority` header value, `10` or `5`.
+    pub fn as_apns_priority(&self) -> &'static str {
+        match self {
+            Priority::High => "10",
+            Priority::Normal => "5",
+        }
+    }
+
+    /// The FCM `priority` field value, `high` or `normal`.
+    pub fn as_fcm_priority(&self) -> &'static str {
+        match self {
+            Priority::High => "high",
+            Priority::Normal => "normal",
+        }
+    }
+}
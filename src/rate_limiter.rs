@@ -0,0 +1,73 @@
+use crate::notification_hub_client::RateLimitHint;
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// A token bucket shared by every clone of a client, allowing bursts of up to
+/// one second's worth of requests and refilling continuously after that.
+/// Quota hints from Azure's responses shrink the bucket or pause it.
+pub(crate) struct RateLimiter {
+    requests_per_second: f64,
+    state: Mutex<BucketState>,
+}
+
+struct BucketState {
+    tokens: f64,
+    last_refill: Instant,
+    paused_until: Option<Instant>,
+}
+
+impl RateLimiter {
+    pub(crate) fn new(requests_per_second: u32) -> Self {
+        let requests_per_second = f64::from(requests_per_second.max(1));
+        Self {
+            requests_per_second,
+            state: Mutex::new(BucketState {
+                tokens: requests_per_second,
+                last_refill: Instant::now(),
+                paused_until: None,
+            }),
+        }
+    }
+
+    /// Waits until a request may be issued, then consumes its token.
+    pub(crate) async fn acquire(&self) {
+        loop {
+            let wait = {
+                let mut state = self.state.lock().unwrap();
+                let now = Instant::now();
+                if let Some(paused_until) = state.paused_until.filter(|until| *until > now) {
+                    paused_until - now
+                } else {
+                    let elapsed = now.duration_since(state.last_refill).as_secs_f64();
+                    state.tokens = (state.tokens + elapsed * self.requests_per_second)
+                        .min(self.requests_per_second);
+                    state.last_refill = now;
+
+                    if state.tokens >= 1.0 {
+                        state.tokens -= 1.0;
+                        return;
+                    }
+
+                    Duration::from_secs_f64((1.0 - state.tokens) / self.requests_per_second)
+                }
+            };
+
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    /// Slows down according to the quota Azure reports: requests beyond the
+    /// remaining quota are not allowed to burst, and once it is used up no
+    /// request is issued until it resets.
+    pub(crate) fn observe(&self, hint: &RateLimitHint) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(remaining) = hint.remaining {
+            state.tokens = state.tokens.min(remaining as f64);
+        }
+        if hint.remaining == Some(0) {
+            let reset_after = hint.reset_after.unwrap_or(Duration::from_secs(1));
+            state.paused_until = Some(Instant::now() + reset_after);
+        }
+    }
+}
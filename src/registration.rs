@@ -0,0 +1,306 @@
+use crate::notification_hub_client::Installation;
+use crate::platform::Platform;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+pub(crate) const ATOM_NAMESPACE: &str = "http://www.w3.org/2005/Atom";
+pub(crate) const SERVICE_BUS_NAMESPACE: &str =
+    "http://schemas.microsoft.com/netservices/2010/10/servicebus/connect";
+
+/// The content type Azure expects for registration Atom entries
+pub(crate) const ATOM_ENTRY_CONTENT_TYPE: &str = "application/atom+xml;type=entry;charset=utf-8";
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct AppleRegistrationDescription {
+    #[serde(default, skip_serializing)]
+    pub registration_id: Option<String>,
+    #[serde(rename = "ETag", default, skip_serializing)]
+    pub etag: Option<String>,
+    #[serde(default, skip_serializing)]
+    pub expiration_time: Option<String>,
+    #[serde(default, with = "tags", skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    pub device_token: String,
+}
+
+/// A registration for the legacy FCM format, which Azure still names `Gcm`.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct FcmRegistrationDescription {
+    #[serde(default, skip_serializing)]
+    pub registration_id: Option<String>,
+    #[serde(rename = "ETag", default, skip_serializing)]
+    pub etag: Option<String>,
+    #[serde(default, skip_serializing)]
+    pub expiration_time: Option<String>,
+    #[serde(default, with = "tags", skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    #[serde(rename = "GcmRegistrationId")]
+    pub fcm_registration_id: String,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct FcmV1RegistrationDescription {
+    #[serde(default, skip_serializing)]
+    pub registration_id: Option<String>,
+    #[serde(rename = "ETag", default, skip_serializing)]
+    pub etag: Option<String>,
+    #[serde(default, skip_serializing)]
+    pub expiration_time: Option<String>,
+    #[serde(default, with = "tags", skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    #[serde(rename = "FcmV1RegistrationId")]
+    pub fcm_v1_registration_id: String,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct WindowsRegistrationDescription {
+    #[serde(default, skip_serializing)]
+    pub registration_id: Option<String>,
+    #[serde(rename = "ETag", default, skip_serializing)]
+    pub etag: Option<String>,
+    #[serde(default, skip_serializing)]
+    pub expiration_time: Option<String>,
+    #[serde(default, with = "tags", skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    pub channel_uri: String,
+}
+
+/// A device registration as stored by the registrations API.
+#[derive(Clone, Debug, Deserialize)]
+pub enum RegistrationDescription {
+    #[serde(rename = "AppleRegistrationDescription")]
+    Apple(AppleRegistrationDescription),
+    #[serde(rename = "GcmRegistrationDescription")]
+    Fcm(FcmRegistrationDescription),
+    #[serde(rename = "FcmV1RegistrationDescription")]
+    FcmV1(FcmV1RegistrationDescription),
+    #[serde(rename = "WindowsRegistrationDescription")]
+    Windows(WindowsRegistrationDescription),
+}
+
+impl RegistrationDescription {
+    pub fn registration_id(&self) -> Option<&str> {
+        match self {
+            RegistrationDescription::Apple(registration) => registration.registration_id.as_deref(),
+            RegistrationDescription::Fcm(registration) => registration.registration_id.as_deref(),
+            RegistrationDescription::FcmV1(registration) => registration.registration_id.as_deref(),
+            RegistrationDescription::Windows(registration) => {
+                registration.registration_id.as_deref()
+            }
+        }
+    }
+
+    pub fn tags(&self) -> &[String] {
+        match self {
+            RegistrationDescription::Apple(registration) => &registration.tags,
+            RegistrationDescription::Fcm(registration) => &registration.tags,
+            RegistrationDescription::FcmV1(registration) => &registration.tags,
+            RegistrationDescription::Windows(registration) => &registration.tags,
+        }
+    }
+
+    /// The PNS handle the registration delivers to: a device token, FCM
+    /// registration ID or channel URI.
+    pub fn pns_handle(&self) -> &str {
+        match self {
+            RegistrationDescription::Apple(registration) => &registration.device_token,
+            RegistrationDescription::Fcm(registration) => &registration.fcm_registration_id,
+            RegistrationDescription::FcmV1(registration) => &registration.fcm_v1_registration_id,
+            RegistrationDescription::Windows(registration) => &registration.channel_uri,
+        }
+    }
+
+    pub fn platform(&self) -> Platform {
+        match self {
+            RegistrationDescription::Apple(_) => Platform::Apple,
+            RegistrationDescription::Fcm(_) => Platform::Fcm,
+            RegistrationDescription::FcmV1(_) => Platform::FcmV1,
+            RegistrationDescription::Windows(_) => Platform::Windows,
+        }
+    }
+
+    /// Wraps the description in the Atom entry envelope the registrations API expects.
+    pub(crate) fn to_atom_entry(&self) -> Result<String, quick_xml::DeError> {
+        let description = match self {
+            RegistrationDescription::Apple(registration) => {
+                DescriptionOut::AppleRegistrationDescription(Namespaced::new(registration))
+            }
+            RegistrationDescription::Fcm(registration) => {
+                DescriptionOut::GcmRegistrationDescription(Namespaced::new(registration))
+            }
+            RegistrationDescription::FcmV1(registration) => {
+                DescriptionOut::FcmV1RegistrationDescription(Namespaced::new(registration))
+            }
+            RegistrationDescription::Windows(registration) => {
+                DescriptionOut::WindowsRegistrationDescription(Namespaced::new(registration))
+            }
+        };
+
+        let entry = EntryOut {
+            xmlns: ATOM_NAMESPACE,
+            content: ContentOut {
+                content_type: "application/xml",
+                description,
+            },
+        };
+
+        quick_xml::se::to_string(&entry)
+    }
+
+    pub(crate) fn from_atom_entry<R: std::io::BufRead>(
+        reader: R,
+    ) -> Result<Self, quick_xml::DeError> {
+        let entry: EntryIn = quick_xml::de::from_reader(reader)?;
+        Ok(entry.content.description)
+    }
+}
+
+/// Converts a registration into the equivalent installation, for migrating
+/// from the registrations API. The registration ID becomes the installation
+/// ID; replace it if devices already have installation IDs of their own.
+impl From<RegistrationDescription> for Installation {
+    fn from(registration: RegistrationDescription) -> Self {
+        let (platform, registration_id, push_channel, tags) = match registration {
+            RegistrationDescription::Apple(registration) => (
+                Platform::Apple,
+                registration.registration_id,
+                registration.device_token,
+                registration.tags,
+            ),
+            RegistrationDescription::Fcm(registration) => (
+                Platform::Fcm,
+                registration.registration_id,
+                registration.fcm_registration_id,
+                registration.tags,
+            ),
+            RegistrationDescription::FcmV1(registration) => (
+                Platform::FcmV1,
+                registration.registration_id,
+                registration.fcm_v1_registration_id,
+                registration.tags,
+            ),
+            RegistrationDescription::Windows(registration) => (
+                Platform::Windows,
+                registration.registration_id,
+                registration.channel_uri,
+                registration.tags,
+            ),
+        };
+
+        Installation {
+            installation_id: registration_id.unwrap_or_default(),
+            user_id: None,
+            last_active_on: None,
+            expiration_time: None,
+            last_update: None,
+            platform,
+            push_channel,
+            expired_push_channel: false,
+            tags,
+            templates: HashMap::new(),
+            etag: None,
+        }
+    }
+}
+
+impl From<AppleRegistrationDescription> for Installation {
+    fn from(registration: AppleRegistrationDescription) -> Self {
+        RegistrationDescription::Apple(registration).into()
+    }
+}
+
+impl From<FcmRegistrationDescription> for Installation {
+    fn from(registration: FcmRegistrationDescription) -> Self {
+        RegistrationDescription::Fcm(registration).into()
+    }
+}
+
+impl From<FcmV1RegistrationDescription> for Installation {
+    fn from(registration: FcmV1RegistrationDescription) -> Self {
+        RegistrationDescription::FcmV1(registration).into()
+    }
+}
+
+impl From<WindowsRegistrationDescription> for Installation {
+    fn from(registration: WindowsRegistrationDescription) -> Self {
+        RegistrationDescription::Windows(registration).into()
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename = "entry")]
+struct EntryOut<'a> {
+    #[serde(rename = "@xmlns")]
+    xmlns: &'static str,
+    content: ContentOut<'a>,
+}
+
+#[derive(Serialize)]
+struct ContentOut<'a> {
+    #[serde(rename = "@type")]
+    content_type: &'static str,
+    #[serde(rename = "$value")]
+    description: DescriptionOut<'a>,
+}
+
+#[derive(Serialize)]
+#[allow(clippy::enum_variant_names)]
+enum DescriptionOut<'a> {
+    AppleRegistrationDescription(Namespaced<'a, AppleRegistrationDescription>),
+    GcmRegistrationDescription(Namespaced<'a, FcmRegistrationDescription>),
+    FcmV1RegistrationDescription(Namespaced<'a, FcmV1RegistrationDescription>),
+    WindowsRegistrationDescription(Namespaced<'a, WindowsRegistrationDescription>),
+}
+
+#[derive(Serialize)]
+struct Namespaced<'a, T> {
+    #[serde(rename = "@xmlns")]
+    xmlns: &'static str,
+    #[serde(flatten)]
+    inner: &'a T,
+}
+
+impl<'a, T> Namespaced<'a, T> {
+    fn new(inner: &'a T) -> Self {
+        Self {
+            xmlns: SERVICE_BUS_NAMESPACE,
+            inner,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct EntryIn {
+    content: ContentIn,
+}
+
+#[derive(Deserialize)]
+struct ContentIn {
+    #[serde(rename = "$value")]
+    description: RegistrationDescription,
+}
+
+/// Registrations carry their tags as a single comma separated element.
+mod tags {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(tags: &[String], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&tags.join(","))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<String>, D::Error> {
+        let tags = String::deserialize(deserializer)?;
+        Ok(tags
+            .split(',')
+            .map(str::trim)
+            .filter(|tag| !tag.is_empty())
+            .map(str::to_string)
+            .collect())
+    }
+}
@@ -0,0 +1,23 @@
+use crate::platform::Platform;
+use hyper::{Method, StatusCode};
+use std::time::Duration;
+
+/// What a single call to Azure cost, passed to the callback registered with
+/// `NotificationHubClient::with_on_request_complete` once it finishes.
+///
+/// Retries made under a retry policy are counted as part of the one call.
+#[derive(Clone, Debug)]
+pub struct RequestMetrics {
+    pub method: Method,
+    /// The request URI, with any SAS signature masked
+    pub uri: String,
+    /// The notification format of a send, or `None` for other requests
+    pub platform: Option<Platform>,
+    /// The final response status, or `None` when no response was received
+    pub status: Option<StatusCode>,
+    pub elapsed: Duration,
+    /// The size of the request body, or zero for a streamed body
+    pub bytes_sent: u64,
+    /// The response's `Content-Length`, when Azure reported one
+    pub bytes_received: Option<u64>,
+}
@@ -0,0 +1,95 @@
+use hyper::header::RETRY_AFTER;
+use hyper::{Body, Method, Response, StatusCode};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// Controls how requests are retried when Azure throttles (429), is
+/// temporarily unavailable (503) or cannot be connected to. Other statuses are
+/// never retried.
+///
+/// Sends (`POST`) and installation patches (`PATCH`) are not idempotent, so
+/// they are only retried when Azure cannot have acted on them: after a 429 or
+/// a failure to connect. A 503 may arrive after a send was accepted, and
+/// retrying it could deliver the notification twice.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    #[serde(with = "crate::config::duration_millis")]
+    pub base_delay: Duration,
+    #[serde(with = "crate::config::duration_millis")]
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(30),
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn new(max_attempts: u32, base_delay: Duration) -> Self {
+        Self {
+            max_attempts,
+            base_delay,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_max_delay(mut self, max_delay: Duration) -> Self {
+        self.max_delay = max_delay;
+        self
+    }
+
+    pub(crate) fn is_retryable(method: &Method, status: StatusCode) -> bool {
+        match status {
+            StatusCode::TOO_MANY_REQUESTS => true,
+            StatusCode::SERVICE_UNAVAILABLE => is_idempotent(method),
+            _ => false,
+        }
+    }
+
+    /// The exponential backoff delay before the retry following `attempt`
+    /// (zero-based), capped at `max_delay`.
+    pub(crate) fn backoff(&self, attempt: u32) -> Duration {
+        let factor = 2u32.saturating_pow(attempt);
+        self.base_delay
+            .checked_mul(factor)
+            .unwrap_or(self.max_delay)
+            .min(self.max_delay)
+    }
+
+    /// The delay to wait before retrying the given response, preferring the
+    /// server supplied `Retry-After` header over the computed backoff.
+    pub(crate) fn delay_for(&self, res: &Response<Body>, attempt: u32) -> Duration {
+        res.headers()
+            .get(RETRY_AFTER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(parse_retry_after)
+            .map(|delay| delay.min(self.max_delay))
+            .unwrap_or_else(|| self.backoff(attempt))
+    }
+}
+
+fn is_idempotent(method: &Method) -> bool {
+    matches!(
+        *method,
+        Method::GET | Method::HEAD | Method::PUT | Method::DELETE
+    )
+}
+
+/// Parses a `Retry-After` value given either as delay seconds or as an HTTP date.
+pub(crate) fn parse_retry_after(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+
+    let date = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    let delay = date.with_timezone(&chrono::Utc) - chrono::Utc::now();
+    Some(delay.to_std().unwrap_or(Duration::ZERO))
+}
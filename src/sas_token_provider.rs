@@ -1,50 +1,161 @@
 use base64::encode as base64encode;
+use chrono::{DateTime, Duration, Utc};
 use hmac::{Hmac, Mac};
 use sha2::Sha256;
+use std::sync::Arc;
 use urlencoding::encode;
 
 #[allow(missing_docs)]
 #[non_exhaustive]
 #[derive(Debug, thiserror::Error)]
 pub enum GenerateSasTokenError {
-    #[error("Failed to decode the given private key: {0}")]
-    DecodePrivateKeyError(base64::DecodeError),
-    #[error("Failed to use the given private key for the hashing algorithm: {0}")]
-    HashingFailed(hmac::digest::InvalidLength),
+    #[error("Failed to decode the given private key")]
+    DecodePrivateKeyError(#[from] base64::DecodeError),
+    #[error("Failed to use the given private key for the hashing algorithm")]
+    HashingFailed(#[from] hmac::digest::InvalidLength),
 }
 
+/// The source of the current time that token expiries are computed from.
+/// Replace it with `SasTokenProvider::with_clock` to produce deterministic
+/// tokens in tests.
+pub trait Clock: Send + Sync {
+    fn now(&self) -> DateTime<Utc>;
+}
+
+/// Reads the system clock. The default for every provider.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+impl<F: Fn() -> DateTime<Utc> + Send + Sync> Clock for F {
+    fn now(&self) -> DateTime<Utc> {
+        self()
+    }
+}
+
+/// Signs requests with a shared access policy's key.
+///
+/// Azure uses the key exactly as it appears in the connection string, even
+/// though it looks like base64. Providers whose key is stored base64 encoded
+/// on top of that can opt in to decoding it with `with_base64_key`.
+#[derive(Clone)]
 pub struct SasTokenProvider {
     pub(crate) sas_key_name: String,
     pub(crate) sas_key_value: String,
+    pub(crate) ttl: Duration,
+    pub(crate) key_is_base64: bool,
+    pub(crate) clock: Arc<dyn Clock>,
 }
 
 impl SasTokenProvider {
+    /// Creates a provider from a shared access policy's key name and key, for
+    /// keys managed outside a connection string.
+    pub fn new(sas_key_name: String, sas_key_value: String) -> Self {
+        Self {
+            sas_key_name,
+            sas_key_value,
+            ttl: Duration::hours(1),
+            key_is_base64: false,
+            clock: Arc::new(SystemClock),
+        }
+    }
+
+    /// Sets how long generated SAS tokens remain valid. Defaults to one hour.
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Changes how long SAS tokens generated from now on remain valid.
+    pub fn set_ttl(&mut self, ttl: Duration) {
+        self.ttl = ttl;
+    }
+
+    /// Decodes the key from base64 before signing with it. Off by default.
+    pub fn with_base64_key(mut self, key_is_base64: bool) -> Self {
+        self.key_is_base64 = key_is_base64;
+        self
+    }
+
+    /// Replaces the clock token expiries are computed from, e.g. with a
+    /// closure returning a fixed time.
+    pub fn with_clock(mut self, clock: impl Clock + 'static) -> Self {
+        self.clock = Arc::new(clock);
+        self
+    }
+
     pub fn generate_sas_token(&self, target_url: &str) -> Result<String, GenerateSasTokenError> {
-        type HmacSHA256 = Hmac<Sha256>;
-        let target_url = target_url.to_lowercase();
-        let expiry_date = chrono::Utc::now() + chrono::Duration::hours(1);
-        let expiry_date_seconds = expiry_date.timestamp();
-        let signature_string = format!(
-            "{}\n{}",
-            &encode(&target_url),
-            &expiry_date_seconds.to_string()
-        );
-
-        let mut hmac_value = HmacSHA256::new_from_slice(self.sas_key_value.as_bytes())
-            .map_err(GenerateSasTokenError::HashingFailed)?;
-
-        hmac_value.update(signature_string.as_bytes());
-        let result = hmac_value.finalize();
-
-        let sas_token = base64encode(result.into_bytes());
-        let sas_token_encoded = encode(&sas_token);
-
-        Ok(format!(
-            "SharedAccessSignature sr={}&sig={}&se={}&skn={}",
-            &encode(&target_url),
-            &sas_token_encoded,
-            &expiry_date_seconds.to_string(),
-            &self.sas_key_name
-        ))
+        if self.key_is_base64 {
+            let key = base64::decode(&self.sas_key_value)
+                .map_err(GenerateSasTokenError::DecodePrivateKeyError)?;
+            sign_sas_token(
+                &self.sas_key_name,
+                &key,
+                target_url,
+                self.clock.now() + self.ttl,
+            )
+        } else {
+            sign_sas_token(
+                &self.sas_key_name,
+                self.sas_key_value.as_bytes(),
+                target_url,
+                self.clock.now() + self.ttl,
+            )
+        }
     }
 }
+
+/// Signs a Shared Access Signature token for `target_url` that expires `ttl`
+/// from now. Usable for any Service Bus resource, such as the management API,
+/// without constructing a client.
+pub fn generate_sas_token(
+    sas_key_name: &str,
+    sas_key_value: &str,
+    target_url: &str,
+    ttl: Duration,
+) -> Result<String, GenerateSasTokenError> {
+    sign_sas_token(
+        sas_key_name,
+        sas_key_value.as_bytes(),
+        target_url,
+        Utc::now() + ttl,
+    )
+}
+
+fn sign_sas_token(
+    sas_key_name: &str,
+    key: &[u8],
+    target_url: &str,
+    expiry_date: DateTime<Utc>,
+) -> Result<String, GenerateSasTokenError> {
+    type HmacSHA256 = Hmac<Sha256>;
+    let target_url = target_url.to_lowercase();
+    let expiry_date_seconds = expiry_date.timestamp();
+    let signature_string = format!(
+        "{}\n{}",
+        &encode(&target_url),
+        &expiry_date_seconds.to_string()
+    );
+
+    let mut hmac_value =
+        HmacSHA256::new_from_slice(key).map_err(GenerateSasTokenError::HashingFailed)?;
+
+    hmac_value.update(signature_string.as_bytes());
+    let result = hmac_value.finalize();
+
+    let sas_token = base64encode(result.into_bytes());
+    let sas_token_encoded = encode(&sas_token);
+
+    Ok(format!(
+        "SharedAccessSignature sr={}&sig={}&se={}&skn={}",
+        &encode(&target_url),
+        &sas_token_encoded,
+        &expiry_date_seconds.to_string(),
+        sas_key_name
+    ))
+}
@@ -0,0 +1,184 @@
+/// The most tags an expression made only of `||` may reference
+const MAX_OR_TAGS: usize = 20;
+/// The most tags an expression using `&&` or `!` may reference
+const MAX_TAGS: usize = 6;
+/// The longest tag Azure accepts
+const MAX_TAG_LEN: usize = 120;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Token<'a> {
+    Tag(&'a str),
+    And,
+    Or,
+    Not,
+    Open,
+    Close,
+}
+
+impl std::fmt::Display for Token<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::Tag(tag) => write!(f, "tag '{}'", tag),
+            Token::And => f.write_str("'&&'"),
+            Token::Or => f.write_str("'||'"),
+            Token::Not => f.write_str("'!'"),
+            Token::Open => f.write_str("'('"),
+            Token::Close => f.write_str("')'"),
+        }
+    }
+}
+
+/// Checks a tag expression locally before it is sent, so that mistakes are
+/// reported without a round trip to Azure.
+///
+/// The expression may combine tags with `&&`, `||`, `!` and parentheses. Tags
+/// may contain letters, digits, and `_ @ # . : - $ { }`. Azure allows up to 20
+/// tags in an expression of only `||`, and up to 6 otherwise.
+pub fn validate_tag_expression(expression: &str) -> Result<(), String> {
+    let tokens = tokenize(expression)?;
+
+    let tags = tokens
+        .iter()
+        .filter(|token| matches!(token, Token::Tag(_)))
+        .count();
+    let only_or = !tokens
+        .iter()
+        .any(|token| matches!(token, Token::And | Token::Not));
+    let max_tags = if only_or { MAX_OR_TAGS } else { MAX_TAGS };
+    if tags > max_tags {
+        return Err(format!(
+            "the expression has {} tags, more than the {} allowed",
+            tags, max_tags
+        ));
+    }
+
+    let mut parser = Parser {
+        tokens,
+        position: 0,
+    };
+    parser.parse_or()?;
+    match parser.tokens.get(parser.position) {
+        None => Ok(()),
+        Some(token) => Err(format!("unexpected {}", token)),
+    }
+}
+
+/// Checks that a single tag only uses the characters Azure allows, so that it
+/// cannot be read as part of an expression. Tags cannot be escaped, so a tag
+/// such as `a||b` is rejected rather than sent as an OR of two tags.
+pub fn validate_tag(tag: &str) -> Result<(), String> {
+    if tag.is_empty() {
+        return Err("a tag is empty".to_string());
+    }
+    if tag.chars().count() > MAX_TAG_LEN {
+        return Err(format!(
+            "tag '{}' is longer than {} characters",
+            tag, MAX_TAG_LEN
+        ));
+    }
+    match tag.chars().find(|c| !is_tag_char(*c)) {
+        Some(c) => Err(format!("tag '{}' contains invalid character '{}'", tag, c)),
+        None => Ok(()),
+    }
+}
+
+/// Joins tags into an expression matching any of them, after checking each
+/// with `validate_tag`.
+pub(crate) fn any_tag_expression(tags: &[&str]) -> Result<String, String> {
+    for tag in tags {
+        validate_tag(tag)?;
+    }
+
+    Ok(tags.join("||"))
+}
+
+fn is_tag_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || "_@#.:-${}".contains(c)
+}
+
+fn tokenize(expression: &str) -> Result<Vec<Token<'_>>, String> {
+    let mut tokens = Vec::new();
+    let mut rest = expression;
+
+    while let Some(c) = rest.chars().next() {
+        let (token, len) = if c.is_whitespace() {
+            rest = &rest[c.len_utf8()..];
+            continue;
+        } else if rest.starts_with("&&") {
+            (Token::And, 2)
+        } else if rest.starts_with("||") {
+            (Token::Or, 2)
+        } else if c == '!' {
+            (Token::Not, 1)
+        } else if c == '(' {
+            (Token::Open, 1)
+        } else if c == ')' {
+            (Token::Close, 1)
+        } else if is_tag_char(c) {
+            let len = rest.find(|c| !is_tag_char(c)).unwrap_or(rest.len());
+            (Token::Tag(&rest[..len]), len)
+        } else {
+            return Err(format!("invalid character '{}'", c));
+        };
+
+        tokens.push(token);
+        rest = &rest[len..];
+    }
+
+    if tokens.is_empty() {
+        return Err("the expression is empty".to_string());
+    }
+
+    Ok(tokens)
+}
+
+struct Parser<'a> {
+    tokens: Vec<Token<'a>>,
+    position: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn next(&mut self) -> Option<Token<'a>> {
+        let token = self.tokens.get(self.position).copied();
+        self.position += 1;
+        token
+    }
+
+    fn peek_is(&self, expected: Token) -> bool {
+        self.tokens.get(self.position) == Some(&expected)
+    }
+
+    fn parse_or(&mut self) -> Result<(), String> {
+        self.parse_and()?;
+        while self.peek_is(Token::Or) {
+            self.position += 1;
+            self.parse_and()?;
+        }
+        Ok(())
+    }
+
+    fn parse_and(&mut self) -> Result<(), String> {
+        self.parse_unary()?;
+        while self.peek_is(Token::And) {
+            self.position += 1;
+            self.parse_unary()?;
+        }
+        Ok(())
+    }
+
+    fn parse_unary(&mut self) -> Result<(), String> {
+        match self.next() {
+            Some(Token::Not) => self.parse_unary(),
+            Some(Token::Open) => {
+                self.parse_or()?;
+                match self.next() {
+                    Some(Token::Close) => Ok(()),
+                    _ => Err("unbalanced parentheses".to_string()),
+                }
+            }
+            Some(Token::Tag(_)) => Ok(()),
+            Some(token) => Err(format!("expected a tag but found {}", token)),
+            None => Err("the expression ends with an operator".to_string()),
+        }
+    }
+}
@@ -0,0 +1,63 @@
+use crate::sas_token_provider::SasTokenProvider;
+use async_trait::async_trait;
+use futures::future::BoxFuture;
+use std::future::Future;
+
+/// The error a `TokenProvider` fails with, which is kept as the source of the
+/// `NotificationRequestError` the request then fails with.
+pub type TokenProviderError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Supplies the `Authorization` header of every request, for credentials that
+/// are not a shared access key held by the client, such as SAS tokens issued
+/// by a central service.
+///
+/// Pass an implementation to `NotificationHubClient::from_custom_token_provider`.
+/// It is called before every request, including retries, so it should cache
+/// tokens itself if obtaining one is expensive.
+#[async_trait]
+pub trait TokenProvider: Send + Sync {
+    /// Returns the full header value, e.g. `SharedAccessSignature sr=...`, for
+    /// a request to the namespace at `target_url`.
+    async fn token(&self, target_url: &str) -> Result<String, TokenProviderError>;
+}
+
+#[async_trait]
+impl TokenProvider for SasTokenProvider {
+    async fn token(&self, target_url: &str) -> Result<String, TokenProviderError> {
+        Ok(self.generate_sas_token(target_url)?)
+    }
+}
+
+type TokenCallback =
+    Box<dyn Fn() -> BoxFuture<'static, Result<String, TokenProviderError>> + Send + Sync>;
+
+/// Authorizes requests with an Azure Active Directory (Microsoft Entra ID)
+/// access token, sent as `Authorization: Bearer <token>`.
+///
+/// The callback is awaited before every request and should return a token
+/// that is valid for Notification Hubs, e.g. one obtained from an
+/// `azure_identity` credential, which caches and refreshes tokens itself.
+/// The principal needs a Notification Hubs role on the namespace or hub.
+pub struct BearerTokenProvider {
+    callback: TokenCallback,
+}
+
+impl BearerTokenProvider {
+    pub fn new<F, Fut>(callback: F) -> Self
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<String, TokenProviderError>> + Send + 'static,
+    {
+        Self {
+            callback: Box::new(move || Box::pin(callback())),
+        }
+    }
+}
+
+#[async_trait]
+impl TokenProvider for BearerTokenProvider {
+    async fn token(&self, _target_url: &str) -> Result<String, TokenProviderError> {
+        let token = (self.callback)().await?;
+        Ok(format!("Bearer {}", token))
+    }
+}
@@ -0,0 +1,151 @@
+use crate::notification_hub_client::NotificationRequest;
+use crate::platform::Platform;
+use hyper::body::Bytes;
+use quick_xml::escape::escape;
+use std::collections::HashMap;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WnsType {
+    Toast,
+    Tile,
+    Raw,
+}
+
+impl WnsType {
+    /// The value sent in the `X-WNS-Type` header.
+    pub fn as_header_value(&self) -> &'static str {
+        match self {
+            WnsType::Toast => "wns/toast",
+            WnsType::Tile => "wns/tile",
+            WnsType::Raw => "wns/raw",
+        }
+    }
+}
+
+/// Builds a Windows Notification Service payload: an XML toast or tile, or an
+/// opaque raw notification.
+#[derive(Clone, Debug)]
+pub struct WnsNotification {
+    wns_type: WnsType,
+    template: String,
+    texts: Vec<String>,
+    launch: Option<String>,
+    raw: Bytes,
+    content_type: Option<String>,
+}
+
+impl WnsNotification {
+    /// A toast using the adaptive `ToastGeneric` template.
+    pub fn toast() -> Self {
+        Self {
+            wns_type: WnsType::Toast,
+            template: "ToastGeneric".to_string(),
+            texts: Vec::new(),
+            launch: None,
+            raw: Bytes::new(),
+            content_type: None,
+        }
+    }
+
+    /// A tile update using the `TileMedium` template.
+    pub fn tile() -> Self {
+        Self {
+            wns_type: WnsType::Tile,
+            template: "TileMedium".to_string(),
+            texts: Vec::new(),
+            launch: None,
+            raw: Bytes::new(),
+            content_type: None,
+        }
+    }
+
+    /// A raw notification delivered to the app as-is.
+    pub fn raw(payload: impl Into<Bytes>) -> Self {
+        Self {
+            wns_type: WnsType::Raw,
+            template: String::new(),
+            texts: Vec::new(),
+            launch: None,
+            raw: payload.into(),
+            content_type: None,
+        }
+    }
+
+    /// Overrides the binding template of a toast or tile.
+    pub fn template(mut self, template: impl Into<String>) -> Self {
+        self.template = template.into();
+        self
+    }
+
+    /// Adds a line of text to the toast or tile. The first line of a toast is its title.
+    pub fn text(mut self, text: impl Into<String>) -> Self {
+        self.texts.push(text.into());
+        self
+    }
+
+    /// Sets the arguments passed to the app when the toast is activated.
+    pub fn launch(mut self, launch: impl Into<String>) -> Self {
+        self.launch = Some(launch.into());
+        self
+    }
+
+    /// Overrides the content type, which defaults to `application/xml` for
+    /// toasts and tiles and `application/octet-stream` for raw notifications.
+    pub fn content_type(mut self, content_type: impl Into<String>) -> Self {
+        self.content_type = Some(content_type.into());
+        self
+    }
+
+    pub fn wns_type(&self) -> WnsType {
+        self.wns_type
+    }
+
+    /// Serializes the notification into its WNS payload.
+    pub fn to_payload(&self) -> Bytes {
+        let root = match self.wns_type {
+            WnsType::Toast => "toast",
+            WnsType::Tile => "tile",
+            WnsType::Raw => return self.raw.clone(),
+        };
+
+        let launch = match &self.launch {
+            Some(launch) => format!(r#" launch="{}""#, escape(launch)),
+            None => String::new(),
+        };
+        let texts: String = self
+            .texts
+            .iter()
+            .map(|text| format!("<text>{}</text>", escape(text)))
+            .collect();
+
+        Bytes::from(format!(
+            r#"<{root}{launch}><visual><binding template="{template}">{texts}</binding></visual></{root}>"#,
+            root = root,
+            launch = launch,
+            template = escape(&self.template),
+            texts = texts,
+        ))
+    }
+}
+
+impl From<WnsNotification> for NotificationRequest {
+    fn from(notification: WnsNotification) -> Self {
+        let content_type = notification.content_type.clone().unwrap_or_else(|| {
+            match notification.wns_type {
+                WnsType::Raw => "application/octet-stream",
+                WnsType::Toast | WnsType::Tile => Platform::Windows.default_content_type().unwrap(),
+            }
+            .to_string()
+        });
+
+        NotificationRequest {
+            headers: HashMap::from([(
+                "X-WNS-Type".to_string(),
+                notification.wns_type.as_header_value().to_string(),
+            )]),
+            message: notification.to_payload(),
+            content_type,
+            platform: Platform::Windows,
+        }
+    }
+}